@@ -0,0 +1,2 @@
+ALTER TABLE emotes
+ADD COLUMN files jsonb NOT NULL DEFAULT '[]';
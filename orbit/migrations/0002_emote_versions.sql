@@ -0,0 +1,10 @@
+ALTER TABLE versions
+ADD COLUMN width integer NOT NULL DEFAULT 0,
+ADD COLUMN height integer NOT NULL DEFAULT 0,
+ADD COLUMN animated boolean NOT NULL DEFAULT false,
+ADD COLUMN files jsonb NOT NULL DEFAULT '[]';
+
+-- Deferred so an emote and its first version can be inserted in the same
+-- transaction.
+ALTER TABLE emotes
+ADD COLUMN active_version_id bigint REFERENCES versions (id) ON DELETE SET NULL DEFERRABLE INITIALLY DEFERRED;
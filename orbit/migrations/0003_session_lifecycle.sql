@@ -0,0 +1,32 @@
+-- Session tokens move out of the primary key so sessions can be listed and
+-- revoked by an id that isn't itself a credential.
+ALTER TABLE sessions
+RENAME COLUMN id TO token;
+
+ALTER TABLE sessions
+ADD COLUMN id bigint,
+ADD COLUMN user_agent text,
+ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+ADD COLUMN last_used_at timestamptz NOT NULL DEFAULT now(),
+ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '30 days';
+
+-- Existing sessions get Snowflake-shaped ids from the current time.
+UPDATE sessions
+SET id = numbered.id
+FROM (
+	SELECT
+		token,
+		((floor(extract(epoch FROM now()) * 1000)::bigint - 1696118400000) << 22)
+			+ row_number() OVER (ORDER BY token) AS id
+	FROM sessions
+) AS numbered
+WHERE sessions.token = numbered.token;
+
+ALTER TABLE sessions
+DROP CONSTRAINT sessions_pkey,
+ALTER COLUMN id SET NOT NULL,
+ADD PRIMARY KEY (id),
+ADD UNIQUE (token);
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
@@ -0,0 +1,5 @@
+-- Plaintext tokens are hashed on startup by `auth::hash_legacy_tokens`, after
+-- which `token` is always NULL.
+ALTER TABLE sessions
+ADD COLUMN token_hash bytea UNIQUE,
+ALTER COLUMN token DROP NOT NULL;
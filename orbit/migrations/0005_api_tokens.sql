@@ -0,0 +1,14 @@
+CREATE TYPE scope AS ENUM ('emotes:write', 'sets:write', 'users:read', 'users:write');
+
+CREATE TABLE api_tokens (
+	id bigint PRIMARY KEY,
+	user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+	name text NOT NULL,
+	token_hash bytea NOT NULL UNIQUE,
+	scopes scope[] NOT NULL DEFAULT '{}',
+	created_at timestamptz NOT NULL DEFAULT now(),
+	last_used_at timestamptz,
+	expires_at timestamptz
+);
+
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
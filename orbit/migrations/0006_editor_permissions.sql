@@ -0,0 +1,6 @@
+CREATE TYPE editor_permission AS ENUM ('manage_emotes', 'rename_sets', 'change_capacity');
+
+-- Existing editors keep everything they could already do.
+ALTER TABLE users_to_editors
+ADD COLUMN permissions editor_permission[] NOT NULL
+DEFAULT '{manage_emotes,rename_sets,change_capacity}';
@@ -0,0 +1,3 @@
+-- The name an emote goes by in one set, in place of its own name.
+ALTER TABLE emotes_to_sets
+ADD COLUMN alias text;
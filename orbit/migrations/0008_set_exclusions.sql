@@ -0,0 +1,6 @@
+-- Emotes a set hides from the sets it inherits from.
+CREATE TABLE set_exclusions (
+	set_id bigint NOT NULL REFERENCES sets (id) ON DELETE CASCADE,
+	emote_id bigint NOT NULL REFERENCES emotes (id) ON DELETE CASCADE,
+	PRIMARY KEY (set_id, emote_id)
+);
@@ -0,0 +1,5 @@
+-- The set a set was forked from, kept so forks can be listed.
+ALTER TABLE sets
+ADD COLUMN origin_id bigint REFERENCES sets (id) ON DELETE SET NULL;
+
+CREATE INDEX sets_origin_id_idx ON sets (origin_id);
@@ -0,0 +1,9 @@
+-- Events shared between instances. Every instance reads them in `seq` order,
+-- so they are only kept for as long as a lagging instance might need them.
+CREATE TABLE events (
+	seq bigserial PRIMARY KEY,
+	payload jsonb NOT NULL,
+	created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX events_created_at_idx ON events (created_at);
@@ -0,0 +1,44 @@
+CREATE TYPE webhook_event AS ENUM (
+	'set_emote_added',
+	'set_emote_removed',
+	'set_emote_renamed',
+	'channel_set_updated',
+	'emote_updated',
+	'emote_deleted',
+	'emote_approved',
+	'emote_rejected'
+);
+
+CREATE TABLE webhooks (
+	id bigint PRIMARY KEY,
+	user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+	url text NOT NULL,
+	secret text NOT NULL,
+	events webhook_event[] NOT NULL,
+	enabled boolean NOT NULL DEFAULT true,
+
+	-- Deliveries that failed in a row, reset by any that succeeds.
+	failures integer NOT NULL DEFAULT 0,
+	created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX webhooks_user_id_idx ON webhooks (user_id);
+
+-- One row per event sent to a webhook, updated after every attempt.
+CREATE TABLE webhook_deliveries (
+	id bigint PRIMARY KEY,
+	webhook_id bigint NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+	event webhook_event NOT NULL,
+	payload jsonb NOT NULL,
+	attempts integer NOT NULL DEFAULT 0,
+	status_code integer,
+	error text,
+
+	-- Null until the delivery succeeds or runs out of attempts.
+	success boolean,
+	created_at timestamptz NOT NULL DEFAULT now(),
+	completed_at timestamptz
+);
+
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id, id);
+CREATE INDEX webhook_deliveries_created_at_idx ON webhook_deliveries (created_at);
@@ -0,0 +1,4 @@
+-- A new user and their channel set reference each other, so the user is
+-- inserted first and its channel set is only checked on commit.
+ALTER TABLE users
+ALTER CONSTRAINT users_channel_set_id_fkey DEFERRABLE INITIALLY IMMEDIATE;
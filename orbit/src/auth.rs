@@ -1,21 +1,80 @@
-use axum::extract::{FromRequestParts, Request};
+use std::ops::Deref;
+use std::time::Duration;
+
+use axum::extract::{FromRequestParts, Request, State};
 use axum::http::request::Parts;
 use axum::http::{header, HeaderMap, HeaderValue};
 use axum::middleware::Next;
 use axum::response::Response;
 use axum::RequestPartsExt;
+use hmac::{Hmac, Mac};
+use orbit_types::models::token::{CreateApiToken, NewApiToken, Scope};
+use orbit_types::models::user::User;
+use orbit_types::Snowflake;
+use rand::RngCore;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use tokio_postgres::GenericClient;
 
-use crate::db::{Conn, Connection};
+use crate::db::{Conn, Connection, Pool};
 use crate::error::{Error, JsonError};
 use crate::{AppState, Result};
 
-pub async fn middleware(Conn(conn): Conn, req: Request, next: Next) -> Result<Response> {
-	verify_token(req.headers(), &conn).await?;
+/// Lets API tokens be told apart from session tokens, and makes them easy to
+/// spot if they're leaked.
+const API_TOKEN_PREFIX: &str = "orbit_";
+
+const MAX_USER_AGENT_LENGTH: usize = 512;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub async fn middleware(
+	State(state): State<AppState>,
+	req: Request,
+	next: Next,
+) -> Result<Response> {
+	// The connection is only needed for the token check, so it's returned to
+	// the pool before the handler runs.
+	let conn = state.pool.get_owned().await.map_err(|_| Error::Generic)?;
+	verify_token(&state.token_key, req.headers(), &conn).await?;
+	drop(conn);
 
 	Ok(next.run(req).await)
 }
 
-pub type AuthUser = orbit_types::models::user::User;
+/// The authenticated user. Requests made with an API token only carry that
+/// token's scopes, so handlers check the scope they need with
+/// [`AuthUser::require`].
+pub struct AuthUser {
+	pub user: User,
+	scopes: Option<Vec<Scope>>,
+}
+
+impl AuthUser {
+	/// Sessions can do anything their user can; API tokens only what their
+	/// scopes allow.
+	pub fn require(&self, scope: Scope) -> Result<()> {
+		match &self.scopes {
+			Some(scopes) if !scopes.contains(&scope) => Err(JsonError::MissingScope(scope).into()),
+			_ => Ok(()),
+		}
+	}
+
+	/// For actions no API token can be scoped to.
+	pub fn require_session(&self) -> Result<()> {
+		match self.scopes {
+			Some(_) => Err(JsonError::SessionRequired.into()),
+			None => Ok(()),
+		}
+	}
+}
+
+impl Deref for AuthUser {
+	type Target = User;
+
+	fn deref(&self) -> &Self::Target {
+		&self.user
+	}
+}
 
 #[axum::async_trait]
 impl FromRequestParts<AppState> for AuthUser {
@@ -27,7 +86,12 @@ impl FromRequestParts<AppState> for AuthUser {
 	) -> Result<Self, Self::Rejection> {
 		let Conn(conn) = parts.extract_with_state::<Conn, AppState>(state).await?;
 
-		let user_id = verify_token(&parts.headers, &conn).await?;
+		let credential = verify_token(&state.token_key, &parts.headers, &conn).await?;
+
+		let (user_id, scopes) = match credential {
+			Credential::Session(session) => (session.user_id, None),
+			Credential::ApiToken { user_id, scopes } => (user_id, Some(scopes)),
+		};
 
 		let user = conn
 			.query_opt("SELECT * FROM users WHERE id = $1", &[&user_id])
@@ -35,28 +99,277 @@ impl FromRequestParts<AppState> for AuthUser {
 			.ok_or(JsonError::UnknownEntity("user".into()))?
 			.into();
 
-		Ok(user)
+		Ok(Self { user, scopes })
+	}
+}
+
+/// The session the current request was authenticated with. API tokens are
+/// rejected, so only sessions can manage sessions and tokens.
+pub struct AuthSession {
+	pub id: i64,
+	pub user_id: i64,
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for AuthSession {
+	type Rejection = Error;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		state: &AppState,
+	) -> Result<Self, Self::Rejection> {
+		let Conn(conn) = parts.extract_with_state::<Conn, AppState>(state).await?;
+
+		match verify_token(&state.token_key, &parts.headers, &conn).await? {
+			Credential::Session(session) => Ok(session),
+			Credential::ApiToken { .. } => Err(JsonError::SessionRequired.into()),
+		}
+	}
+}
+
+enum Credential {
+	Session(AuthSession),
+	ApiToken { user_id: i64, scopes: Vec<Scope> },
+}
+
+/// Keys the hash session tokens are stored as, so the `sessions` table alone
+/// can't be used to authenticate.
+pub struct TokenKey(Vec<u8>);
+
+impl TokenKey {
+	pub fn new(key: impl Into<Vec<u8>>) -> Self {
+		let key = key.into();
+		assert!(
+			key.len() >= 32,
+			"Session token key must be at least 32 bytes"
+		);
+
+		Self(key)
+	}
+
+	pub fn hash(&self, token: &str) -> Vec<u8> {
+		let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts any key length");
+		mac.update(token.as_bytes());
+
+		mac.finalize().into_bytes().to_vec()
 	}
 }
 
-async fn verify_token(headers: &HeaderMap<HeaderValue>, conn: &Connection) -> Result<String> {
+/// Only the exact `Bearer <token>` form is accepted.
+fn bearer_token(headers: &HeaderMap<HeaderValue>) -> Option<&str> {
 	let token = headers
-		.get(header::AUTHORIZATION)
-		.and_then(|value| value.to_str().ok())
-		.and_then(|value| {
-			if value.starts_with("Bearer") {
-				Some(value.trim_start_matches("Bearer ").to_string())
-			} else {
-				None
-			}
-		})
-		.ok_or(JsonError::Unauthorized)?;
+		.get(header::AUTHORIZATION)?
+		.to_str()
+		.ok()?
+		.strip_prefix("Bearer ")?;
 
-	let user_id = conn
-		.query_opt("SELECT user_id FROM sessions WHERE id = $1", &[&token])
+	(!token.is_empty() && !token.contains(char::is_whitespace)).then_some(token)
+}
+
+/// Looks up the session or API token for a bearer token. Using a session
+/// pushes its expiry back, so only sessions left idle for the whole TTL
+/// expire. API tokens keep the expiry they were created with.
+async fn verify_token(
+	key: &TokenKey,
+	headers: &HeaderMap<HeaderValue>,
+	conn: &Connection,
+) -> Result<Credential> {
+	let token = bearer_token(headers).ok_or(JsonError::Unauthorized)?;
+	let hash = key.hash(token);
+
+	let row = if token.starts_with(API_TOKEN_PREFIX) {
+		conn.query_opt(
+			"
+			UPDATE api_tokens
+			SET last_used_at = now()
+			WHERE
+				token_hash = $1
+				AND (expires_at IS NULL OR expires_at > now())
+			RETURNING id, user_id, scopes, token_hash
+			",
+			&[&hash],
+		)
 		.await?
-		.ok_or(JsonError::InvalidToken)?
-		.get(0);
+	} else {
+		conn.query_opt(
+			"
+			UPDATE sessions
+			SET
+				last_used_at = now(),
+				expires_at = now() + interval '30 days'
+			WHERE token_hash = $1 AND expires_at > now()
+			RETURNING id, user_id, token_hash
+			",
+			&[&hash],
+		)
+		.await?
+	}
+	.ok_or(JsonError::InvalidToken)?;
+
+	// The lookup already matched on the hash; comparing again in constant time
+	// keeps the accept/reject decision itself free of timing differences.
+	let stored: Vec<u8> = row.get("token_hash");
+
+	if !bool::from(stored.as_slice().ct_eq(hash.as_slice())) {
+		return Err(JsonError::InvalidToken.into());
+	}
+
+	if token.starts_with(API_TOKEN_PREFIX) {
+		Ok(Credential::ApiToken {
+			user_id: row.get("user_id"),
+			scopes: row.get("scopes"),
+		})
+	} else {
+		Ok(Credential::Session(AuthSession {
+			id: row.get("id"),
+			user_id: row.get("user_id"),
+		}))
+	}
+}
+
+/// 256 bits from the OS RNG, hex encoded.
+pub fn random_token() -> String {
+	let mut bytes = [0u8; 32];
+	rand::rngs::OsRng.fill_bytes(&mut bytes);
+
+	hex::encode(bytes)
+}
+
+/// Creates a session and returns its token. The token is only ever seen here;
+/// the database keeps its hash.
+pub async fn create_session(
+	client: &impl GenericClient,
+	key: &TokenKey,
+	user_id: i64,
+	user_agent: Option<&str>,
+) -> Result<String> {
+	let token = random_token();
+	let user_agent = user_agent.map(|user_agent| {
+		user_agent
+			.chars()
+			.take(MAX_USER_AGENT_LENGTH)
+			.collect::<String>()
+	});
+
+	client
+		.execute(
+			"
+			INSERT INTO sessions (id, token_hash, user_id, user_agent, expires_at)
+			VALUES ($1, $2, $3, $4, now() + interval '30 days')
+			",
+			&[
+				&Snowflake::new().0,
+				&key.hash(&token),
+				&user_id,
+				&user_agent,
+			],
+		)
+		.await?;
+
+	Ok(token)
+}
+
+/// Replaces the plaintext tokens of sessions created before tokens were hashed.
+/// Runs on startup, after migrations, until no plaintext tokens are left.
+pub async fn hash_legacy_tokens(pool: &Pool, key: &TokenKey) {
+	let mut conn = pool
+		.get()
+		.await
+		.expect("Failed to retrieve database connection");
 
-	Ok(user_id)
+	let transaction = conn
+		.transaction()
+		.await
+		.expect("Failed to start token migration");
+
+	let rows = transaction
+		.query(
+			"SELECT id, token FROM sessions WHERE token IS NOT NULL FOR UPDATE",
+			&[],
+		)
+		.await
+		.expect("Failed to read legacy session tokens");
+
+	for row in &rows {
+		let id: i64 = row.get("id");
+		let token: String = row.get("token");
+
+		transaction
+			.execute(
+				"UPDATE sessions SET token_hash = $2, token = NULL WHERE id = $1",
+				&[&id, &key.hash(&token)],
+			)
+			.await
+			.expect("Failed to hash legacy session token");
+	}
+
+	transaction
+		.commit()
+		.await
+		.expect("Failed to commit token migration");
+
+	if !rows.is_empty() {
+		tracing::info!(count = rows.len(), "Hashed legacy session tokens");
+	}
+}
+
+/// Creates an API token and returns it with its secret. Like session tokens,
+/// the secret is only stored as a hash.
+pub async fn create_api_token(
+	client: &impl GenericClient,
+	key: &TokenKey,
+	user_id: i64,
+	body: &CreateApiToken,
+) -> Result<NewApiToken> {
+	let token = format!("{API_TOKEN_PREFIX}{}", random_token());
+
+	let row = client
+		.query_one(
+			"
+			INSERT INTO api_tokens (id, user_id, name, token_hash, scopes, expires_at)
+			VALUES ($1, $2, $3, $4, $5, $6)
+			RETURNING *
+			",
+			&[
+				&Snowflake::new().0,
+				&user_id,
+				&body.name,
+				&key.hash(&token),
+				&body.scopes,
+				&body.expires_at,
+			],
+		)
+		.await?;
+
+	Ok(NewApiToken {
+		token: row.into(),
+		secret: token,
+	})
+}
+
+/// Periodically deletes expired sessions. Expired sessions are already
+/// rejected by [`verify_token`], so this only keeps the table small.
+pub fn spawn_session_sweeper(pool: Pool) {
+	tokio::spawn(async move {
+		let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+
+		loop {
+			interval.tick().await;
+
+			let result = async {
+				let conn = pool.get().await.map_err(|_| Error::Generic)?;
+
+				conn.execute("DELETE FROM sessions WHERE expires_at <= now()", &[])
+					.await
+					.map_err(Error::from)
+			}
+			.await;
+
+			match result {
+				Ok(0) => (),
+				Ok(count) => tracing::debug!(count, "Deleted expired sessions"),
+				Err(err) => tracing::error!(?err, "Failed to delete expired sessions"),
+			}
+		}
+	});
 }
@@ -8,14 +8,111 @@ use crate::AppState;
 
 pub type Pool = bb8::Pool<PostgresConnectionManager<NoTls>>;
 
+/// Schema changes owned by this service, applied in order on startup.
+const MIGRATIONS: &[(&str, &str)] = &[
+	(
+		"0001_emote_files",
+		include_str!("../migrations/0001_emote_files.sql"),
+	),
+	(
+		"0002_emote_versions",
+		include_str!("../migrations/0002_emote_versions.sql"),
+	),
+	(
+		"0003_session_lifecycle",
+		include_str!("../migrations/0003_session_lifecycle.sql"),
+	),
+	(
+		"0004_session_token_hashes",
+		include_str!("../migrations/0004_session_token_hashes.sql"),
+	),
+	(
+		"0005_api_tokens",
+		include_str!("../migrations/0005_api_tokens.sql"),
+	),
+	(
+		"0006_editor_permissions",
+		include_str!("../migrations/0006_editor_permissions.sql"),
+	),
+	(
+		"0007_set_emote_aliases",
+		include_str!("../migrations/0007_set_emote_aliases.sql"),
+	),
+	(
+		"0008_set_exclusions",
+		include_str!("../migrations/0008_set_exclusions.sql"),
+	),
+	(
+		"0009_set_forks",
+		include_str!("../migrations/0009_set_forks.sql"),
+	),
+	("0010_events", include_str!("../migrations/0010_events.sql")),
+	(
+		"0011_webhooks",
+		include_str!("../migrations/0011_webhooks.sql"),
+	),
+	(
+		"0012_deferrable_channel_sets",
+		include_str!("../migrations/0012_deferrable_channel_sets.sql"),
+	),
+];
+
 pub async fn init_db(url: String) -> Pool {
 	let manager = PostgresConnectionManager::new_from_stringlike(url, NoTls)
 		.expect("Invalid connection string");
 	let pool = bb8::Pool::builder().build(manager).await.unwrap();
 
+	migrate(&pool).await;
+
 	pool
 }
 
+async fn migrate(pool: &Pool) {
+	let mut conn = pool
+		.get()
+		.await
+		.expect("Failed to retrieve database connection");
+
+	conn.batch_execute(
+		"
+		CREATE TABLE IF NOT EXISTS migrations (
+			name text PRIMARY KEY,
+			applied_at timestamptz NOT NULL DEFAULT now()
+		)
+		",
+	)
+	.await
+	.expect("Failed to create migrations table");
+
+	for (name, sql) in MIGRATIONS {
+		let transaction = conn.transaction().await.expect("Failed to start migration");
+
+		let applied = transaction
+			.execute(
+				"INSERT INTO migrations (name) VALUES ($1) ON CONFLICT DO NOTHING",
+				&[name],
+			)
+			.await
+			.expect("Failed to record migration");
+
+		if applied == 0 {
+			continue;
+		}
+
+		transaction
+			.batch_execute(sql)
+			.await
+			.unwrap_or_else(|err| panic!("Migration `{name}` failed: {err}"));
+
+		transaction
+			.commit()
+			.await
+			.expect("Failed to commit migration");
+
+		tracing::info!("Applied migration `{name}`");
+	}
+}
+
 pub type Connection = bb8::PooledConnection<'static, PostgresConnectionManager<NoTls>>;
 
 pub struct Conn(pub Connection);
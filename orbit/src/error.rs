@@ -1,6 +1,8 @@
+use axum::extract::multipart::MultipartError;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
+use orbit_types::models::token::Scope;
 use serde_json::json;
 
 #[derive(thiserror::Error, Debug)]
@@ -20,6 +22,12 @@ pub enum Error {
 	#[error("{0}")]
 	Conflict(String),
 
+	#[error("{0}")]
+	PayloadTooLarge(String),
+
+	#[error("{0}")]
+	Image(#[from] orbit_image::Error),
+
 	#[error("422 Unprocessable Entity")]
 	UnprocessableEntity,
 
@@ -37,6 +45,9 @@ pub enum Error {
 
 	#[error("500 Internal Server Error (Search")]
 	Search(#[from] meilisearch_sdk::Error),
+
+	#[error("502 Bad Gateway (Twitch)")]
+	Twitch(String),
 }
 
 impl Error {
@@ -49,8 +60,13 @@ impl Error {
 			Unauthorized(_) => StatusCode::UNAUTHORIZED,
 			Forbidden(_) => StatusCode::FORBIDDEN,
 			Conflict(_) => StatusCode::CONFLICT,
+			PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+			Image(orbit_image::Error::TooLarge { .. }) => StatusCode::PAYLOAD_TOO_LARGE,
+			Image(orbit_image::Error::Encode(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+			Image(_) => StatusCode::UNPROCESSABLE_ENTITY,
 			UnprocessableEntity => StatusCode::UNPROCESSABLE_ENTITY,
 			Generic | Cdn | Json(_) | Database(_) | Search(_) => StatusCode::INTERNAL_SERVER_ERROR,
+			Twitch(_) => StatusCode::BAD_GATEWAY,
 		}
 	}
 }
@@ -67,6 +83,12 @@ impl IntoResponse for Error {
 			Self::Search(ref err) => {
 				tracing::error!(?err);
 			}
+			Self::Twitch(ref err) => {
+				tracing::error!(?err);
+			}
+			Self::Image(ref err @ orbit_image::Error::Encode(_)) => {
+				tracing::error!(?err);
+			}
 			_ => (),
 		}
 
@@ -76,6 +98,21 @@ impl IntoResponse for Error {
 	}
 }
 
+impl From<MultipartError> for Error {
+	fn from(value: MultipartError) -> Self {
+		match value.status() {
+			StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge(value.body_text()),
+			_ => Self::BadRequest(value.body_text()),
+		}
+	}
+}
+
+impl From<reqwest::Error> for Error {
+	fn from(value: reqwest::Error) -> Self {
+		Self::Twitch(value.to_string())
+	}
+}
+
 impl From<JsonError> for Error {
 	fn from(value: JsonError) -> Self {
 		use self::Error::*;
@@ -98,6 +135,15 @@ pub enum JsonError {
 	#[error("Unknown {0}.")]
 	UnknownEntity(String),
 
+	#[error("Missing field `{0}`.")]
+	MissingField(String),
+
+	#[error("Invalid field `{0}`.")]
+	InvalidField(String),
+
+	#[error("Nothing to update.")]
+	NothingToUpdate,
+
 	#[error("User cannot add themselves as an editor to their own channel.")]
 	UserCannotAddSelf,
 
@@ -107,11 +153,53 @@ pub enum JsonError {
 	#[error("Invalid bearer token.")]
 	InvalidToken,
 
+	#[error("Invalid or expired OAuth state.")]
+	InvalidOAuthState,
+
 	#[error("Forbidden.")]
 	Forbidden,
 
+	#[error("Token is missing the `{0}` scope.")]
+	MissingScope(Scope),
+
+	#[error("This requires a session rather than an API token.")]
+	SessionRequired,
+
 	#[error("Color already exists.")]
 	ColorExists,
+
+	#[error("The active version of an emote cannot be deleted.")]
+	ActiveVersion,
+
+	#[error("Emote set is full.")]
+	SetFull,
+
+	#[error("An emote named `{0}` is already in this set.")]
+	NameConflict(String),
+
+	#[error("Capacity must be between 1 and {0}.")]
+	InvalidCapacity(i32),
+
+	#[error("Capacity cannot be lower than the number of emotes in the set.")]
+	CapacityBelowUsage,
+
+	#[error("Emote set does not belong to this user.")]
+	SetNotOwned,
+
+	#[error("Emote set has more emotes than your sets can hold ({0}).")]
+	ForkTooLarge(i32),
+
+	#[error("An emote set cannot inherit from itself.")]
+	SetCycle,
+
+	#[error("Emote sets cannot be nested more than {0} deep.")]
+	InheritanceTooDeep(i32),
+
+	#[error("Cannot subscribe to more than {0} things at once.")]
+	TooManySubscriptions(usize),
+
+	#[error("Cannot have more than {0} webhooks.")]
+	TooManyWebhooks(i64),
 }
 
 impl JsonError {
@@ -119,11 +207,21 @@ impl JsonError {
 		use self::JsonError::*;
 
 		match self {
-			UserCannotAddSelf => 400,
+			MissingField(_)
+			| InvalidField(_)
+			| NothingToUpdate
+			| UserCannotAddSelf
+			| InvalidOAuthState
+			| InvalidCapacity(_)
+			| InheritanceTooDeep(_)
+			| SetNotOwned
+			| TooManySubscriptions(_)
+			| TooManyWebhooks(_) => 400,
 			Unauthorized | InvalidToken => 401,
-			Forbidden => 403,
+			Forbidden | MissingScope(_) | SessionRequired => 403,
 			UnknownEntity(_) => 404,
-			ColorExists => 409,
+			ColorExists | ActiveVersion | SetFull | NameConflict(_) | CapacityBelowUsage
+			| ForkTooLarge(_) | SetCycle => 409,
 		}
 	}
 }
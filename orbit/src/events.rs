@@ -0,0 +1,414 @@
+//! Fans events out to every instance's listeners.
+//!
+//! Published events are written to the `events` table and announced with
+//! `NOTIFY`. Each instance keeps a dedicated connection outside the pool that
+//! `LISTEN`s for them and reads new rows in order, so every instance sees the
+//! same events under the same sequence numbers. A client can then resume
+//! against any instance, which keeps the most recent events around for
+//! replaying.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::future::poll_fn;
+use orbit_types::models::event::Event;
+use tokio::sync::{broadcast, mpsc};
+use tokio_postgres::types::Json as Jsonb;
+use tokio_postgres::{AsyncMessage, Client, NoTls};
+
+use crate::db::Pool;
+use crate::error::Error;
+use crate::routes::sets::MAX_INHERITANCE_DEPTH;
+use crate::webhooks::Webhooks;
+use crate::Result;
+
+/// How many events a slow subscriber can fall behind before it starts missing
+/// them.
+const CAPACITY: usize = 1024;
+
+/// How many past events are kept for replaying.
+const LOG_SIZE: usize = 4096;
+
+const CHANNEL: &str = "orbit_events";
+
+/// Held while publishing so events commit, and are read, in `seq` order.
+const PUBLISH_LOCK: i64 = 0x6f72_6265_7674;
+
+const FETCH_SIZE: i64 = 1000;
+
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// How long events stay in the table, which bounds how long an instance's
+/// listener can be disconnected without missing any.
+const RETENTION: &str = "1 hour";
+
+#[derive(Debug)]
+pub struct Dispatch {
+	pub seq: u64,
+	pub event: Event,
+
+	/// The sets that see the change: those the event names, and every set
+	/// inheriting from them that doesn't exclude the emote on the way.
+	pub set_ids: Vec<i64>,
+}
+
+struct Log {
+	/// The number the next event should have. Numbering starts at 1 so that
+	/// 0 can mean "nothing seen yet".
+	next_seq: u64,
+	recent: VecDeque<Arc<Dispatch>>,
+}
+
+#[derive(Clone)]
+pub struct Events {
+	publisher: mpsc::UnboundedSender<Event>,
+	sender: broadcast::Sender<Arc<Dispatch>>,
+	log: Arc<Mutex<Log>>,
+}
+
+impl Events {
+	/// Starts publishing through `pool` and listening on a connection of its
+	/// own to `database_url`. Events published here are also handed to
+	/// `webhooks`.
+	pub fn spawn(pool: Pool, database_url: String, webhooks: Webhooks) -> Self {
+		let (publisher, receiver) = mpsc::unbounded_channel();
+		let (sender, _) = broadcast::channel(CAPACITY);
+
+		let events = Self {
+			publisher,
+			sender,
+			log: Arc::new(Mutex::new(Log {
+				next_seq: 1,
+				recent: VecDeque::with_capacity(LOG_SIZE),
+			})),
+		};
+
+		tokio::spawn(publish_events(pool.clone(), webhooks, receiver));
+		tokio::spawn(listen_forever(database_url, events.clone()));
+		tokio::spawn(prune_events(pool));
+
+		events
+	}
+
+	/// Publishing never fails the request that caused it. The event reaches
+	/// subscribers here once it has come back through the listener, like
+	/// everyone else's.
+	pub fn publish(&self, event: Event) {
+		if self.publisher.send(event).is_err() {
+			tracing::error!("Event publisher has stopped");
+		}
+	}
+
+	pub fn subscribe(&self) -> broadcast::Receiver<Arc<Dispatch>> {
+		self.sender.subscribe()
+	}
+
+	/// The newest event's number, or 0 if there hasn't been one.
+	pub fn last_seq(&self) -> u64 {
+		self.log.lock().unwrap().next_seq - 1
+	}
+
+	/// Every event after `seq`, or `None` if some of them have already been
+	/// dropped from the log.
+	pub fn since(&self, seq: u64) -> Option<Vec<Arc<Dispatch>>> {
+		let log = self.log.lock().unwrap();
+
+		if seq >= log.next_seq {
+			return None;
+		}
+
+		let oldest = log
+			.recent
+			.front()
+			.map_or(log.next_seq, |dispatch| dispatch.seq);
+
+		if seq + 1 < oldest {
+			return None;
+		}
+
+		Some(
+			log.recent
+				.iter()
+				.filter(|dispatch| dispatch.seq > seq)
+				.cloned()
+				.collect(),
+		)
+	}
+
+	fn deliver(&self, seq: u64, event: Event, set_ids: Vec<i64>) {
+		let mut log = self.log.lock().unwrap();
+
+		// Events that were pruned before this instance read them, or numbers
+		// skipped by a publish that failed, leave a gap. Nothing from before it
+		// can be replayed safely.
+		if seq != log.next_seq {
+			log.recent.clear();
+		}
+
+		log.next_seq = seq + 1;
+
+		if log.recent.len() == LOG_SIZE {
+			log.recent.pop_front();
+		}
+
+		let dispatch = Arc::new(Dispatch {
+			seq,
+			event,
+			set_ids,
+		});
+		log.recent.push_back(dispatch.clone());
+
+		// Sending under the lock keeps subscribers seeing events in order.
+		let _ = self.sender.send(dispatch);
+	}
+
+	/// Reads and delivers everything published since the last event this
+	/// instance saw.
+	async fn catch_up(&self, client: &Client) -> Result<()> {
+		loop {
+			let next_seq = self.log.lock().unwrap().next_seq as i64;
+
+			let rows = client
+				.query(
+					"SELECT seq, payload FROM events WHERE seq >= $1 ORDER BY seq LIMIT $2",
+					&[&next_seq, &FETCH_SIZE],
+				)
+				.await?;
+
+			let count = rows.len();
+
+			for row in rows {
+				let seq: i64 = row.get("seq");
+				let Jsonb(event): Jsonb<Event> = row.get("payload");
+				let set_ids = inheriting_sets(client, &event).await?;
+
+				self.deliver(seq as u64, event, set_ids);
+			}
+
+			if count < FETCH_SIZE as usize {
+				return Ok(());
+			}
+		}
+	}
+}
+
+/// Resolved once per instance as events are read, rather than by every
+/// connection that's filtering them. Walks inheritance like the resolved set
+/// listing does.
+async fn inheriting_sets(client: &Client, event: &Event) -> Result<Vec<i64>> {
+	let (set_ids, emote_id) = match event {
+		Event::SetEmoteAdded {
+			set_id, emote_id, ..
+		}
+		| Event::SetEmoteRemoved { set_id, emote_id }
+		| Event::SetEmoteRenamed {
+			set_id, emote_id, ..
+		} => (vec![*set_id], *emote_id),
+		Event::EmoteUpdated {
+			emote_id, set_ids, ..
+		}
+		| Event::EmoteDeleted {
+			emote_id, set_ids, ..
+		} => (set_ids.clone(), *emote_id),
+		Event::ChannelSetUpdated { .. }
+		| Event::EmoteApproved { .. }
+		| Event::EmoteRejected { .. } => return Ok(vec![]),
+	};
+
+	if set_ids.is_empty() {
+		return Ok(set_ids);
+	}
+
+	let set_ids = client
+		.query(
+			"
+			WITH RECURSIVE inheriting AS (
+				SELECT id, 0 AS depth, false AS excluded
+				FROM unnest($1::int8[]) AS id
+
+				UNION ALL
+
+				SELECT
+					sets.id,
+					inheriting.depth + 1,
+					inheriting.excluded OR EXISTS (
+						SELECT 1
+						FROM set_exclusions AS exclusion
+						WHERE exclusion.set_id = sets.id AND exclusion.emote_id = $2
+					)
+				FROM
+					sets
+					JOIN inheriting ON sets.parent_id = inheriting.id
+				WHERE inheriting.depth < $3
+			)
+			SELECT DISTINCT id
+			FROM inheriting
+			WHERE NOT excluded
+			",
+			&[&set_ids, &emote_id, &MAX_INHERITANCE_DEPTH],
+		)
+		.await?
+		.iter()
+		.map(|row| row.get("id"))
+		.collect();
+
+	Ok(set_ids)
+}
+
+/// Webhooks are sent from here rather than from the listener, so only the
+/// instance an event came from sends them.
+async fn publish_events(
+	pool: Pool,
+	webhooks: Webhooks,
+	mut receiver: mpsc::UnboundedReceiver<Event>,
+) {
+	while let Some(event) = receiver.recv().await {
+		for attempt in 1..=MAX_ATTEMPTS {
+			match publish(&pool, &event).await {
+				Ok(()) => {
+					webhooks.send(event);
+					break;
+				}
+				Err(err) if attempt < MAX_ATTEMPTS => {
+					tracing::warn!(?err, attempt, "Publishing event failed, retrying");
+					tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+				}
+				Err(err) => tracing::error!(?err, ?event, "Publishing event failed, giving up"),
+			}
+		}
+	}
+}
+
+async fn publish(pool: &Pool, event: &Event) -> Result<()> {
+	let mut conn = pool.get().await.map_err(|_| Error::Generic)?;
+	let transaction = conn.transaction().await?;
+
+	// Without the lock a later `seq` could commit first, and listeners, which
+	// only read forward, would never see the earlier one.
+	transaction
+		.execute("SELECT pg_advisory_xact_lock($1)", &[&PUBLISH_LOCK])
+		.await?;
+
+	transaction
+		.execute("INSERT INTO events (payload) VALUES ($1)", &[&Jsonb(event)])
+		.await?;
+
+	// Notifications are only sent on commit, once the row is visible.
+	transaction
+		.batch_execute(&format!("NOTIFY {CHANNEL}"))
+		.await?;
+
+	transaction.commit().await?;
+
+	Ok(())
+}
+
+/// Keeps a listener connected, waiting longer between each failed attempt.
+async fn listen_forever(database_url: String, events: Events) {
+	let mut delay = RECONNECT_DELAY;
+
+	loop {
+		match listen(&database_url, &events).await {
+			Ok(()) => delay = RECONNECT_DELAY,
+			Err(err) => tracing::error!(?err, "Event listener failed"),
+		}
+
+		tracing::warn!(?delay, "Event listener disconnected, reconnecting");
+		tokio::time::sleep(delay).await;
+
+		delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+	}
+}
+
+/// Listens until the connection drops.
+async fn listen(database_url: &str, events: &Events) -> Result<()> {
+	let (client, mut connection) = tokio_postgres::connect(database_url, NoTls).await?;
+	let (notify, mut notified) = mpsc::unbounded_channel();
+
+	// The connection has to be polled by hand to see notifications. It also
+	// drives the client's queries while doing so.
+	let driver = tokio::spawn(async move {
+		while let Some(message) = poll_fn(|cx| connection.poll_message(cx)).await {
+			match message? {
+				// Nobody is waiting any more once the listener has given up, and
+				// then this task is about to be aborted anyway.
+				AsyncMessage::Notification(_) => {
+					let _ = notify.send(());
+				}
+				AsyncMessage::Notice(notice) => tracing::debug!(?notice, "Postgres notice"),
+				_ => (),
+			}
+		}
+
+		Ok::<_, tokio_postgres::Error>(())
+	});
+
+	let result = async {
+		client.batch_execute(&format!("LISTEN {CHANNEL}")).await?;
+
+		// A new instance starts from the newest event rather than replaying
+		// the whole table.
+		if events.last_seq() == 0 {
+			let last: Option<i64> = client
+				.query_one("SELECT max(seq) FROM events", &[])
+				.await?
+				.get(0);
+
+			if let Some(last) = last {
+				events.log.lock().unwrap().next_seq = last as u64 + 1;
+			}
+		}
+
+		// Anything published while disconnected is read before waiting.
+		events.catch_up(&client).await?;
+
+		tracing::info!("Listening for events");
+
+		while notified.recv().await.is_some() {
+			// One read picks up every event announced so far.
+			while notified.try_recv().is_ok() {}
+
+			events.catch_up(&client).await?;
+		}
+
+		Ok(())
+	}
+	.await;
+
+	driver.abort();
+
+	result
+}
+
+async fn prune_events(pool: Pool) {
+	let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+	loop {
+		interval.tick().await;
+
+		let result = async {
+			let conn = pool.get().await.map_err(|_| Error::Generic)?;
+
+			conn.execute(
+				&format!("DELETE FROM events WHERE created_at < now() - interval '{RETENTION}'"),
+				&[],
+			)
+			.await
+			.map_err(Error::from)
+		}
+		.await;
+
+		match result {
+			Ok(0) => (),
+			Ok(count) => tracing::debug!(count, "Pruned old events"),
+			Err(err) => tracing::error!(?err, "Failed to prune old events"),
+		}
+	}
+}
@@ -1,8 +1,16 @@
 mod auth;
 mod db;
 mod error;
+mod events;
+mod policy;
 mod routes;
+mod search;
+mod storage;
+mod twitch;
+mod webhooks;
 
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 use axum::http::Request;
@@ -10,20 +18,34 @@ use axum::Router;
 use meilisearch_sdk::client::Client as MeilisearchClient;
 use shuttle_secrets::SecretStore;
 use tower::ServiceBuilder;
+use tower_http::services::ServeDir;
 use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
 
+use crate::auth::TokenKey;
 use crate::error::Error;
+use crate::events::Events;
+use crate::search::SearchSync;
+use crate::storage::{LocalStorage, S3Storage, Storage};
+use crate::twitch::Twitch;
+use crate::webhooks::Webhooks;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(Clone)]
 struct AppState {
-	s3: aws_sdk_s3::Client,
+	storage: Arc<dyn Storage>,
 	ms: MeilisearchClient,
+	search: SearchSync,
+	events: Events,
 	pool: db::Pool,
+	twitch: Arc<Twitch>,
+	token_key: Arc<TokenKey>,
+
+	/// Where users are sent back to after logging in.
+	frontend_url: Arc<str>,
 }
 
 #[shuttle_runtime::main]
@@ -35,27 +57,76 @@ async fn main(#[shuttle_secrets::Secrets] secrets: SecretStore) -> shuttle_axum:
 		.with(fmt::layer())
 		.init();
 
-	let s3_config = aws_config::load_from_env().await;
-
 	let meilisearch_url = get_secret(&secrets, "MEILISEARCH_URL");
 	let meilisearch_key = get_secret(&secrets, "MEILISEARCH_KEY");
 	let database_url = get_secret(&secrets, "DATABASE_URL");
 
 	let ms = MeilisearchClient::new(meilisearch_url, Some(meilisearch_key));
 
-	ms.index("emotes")
-		.set_searchable_attributes(["name"])
+	search::configure(&search::emotes(&ms))
 		.await
-		.expect("Failed to set searchable attributes");
+		.expect("Failed to configure search index");
+
+	let cdn_url = get_secret(&secrets, "CDN_URL");
+	let storage_prefix = secrets.get("STORAGE_PREFIX").unwrap_or_default();
+
+	// Local storage is served by the API itself so uploads work without AWS.
+	let mut local_root = None;
+	let storage: Arc<dyn Storage> = match secrets.get("STORAGE_BACKEND").as_deref() {
+		Some("local") => {
+			let root = PathBuf::from(get_secret(&secrets, "STORAGE_PATH"));
+			local_root = Some(root.clone());
+
+			Arc::new(LocalStorage::new(root, storage_prefix, cdn_url))
+		}
+		Some("s3") | None => {
+			let s3_config = aws_config::load_from_env().await;
+
+			Arc::new(S3Storage::new(
+				aws_sdk_s3::Client::new(&s3_config),
+				get_secret(&secrets, "STORAGE_BUCKET"),
+				storage_prefix,
+				cdn_url,
+			))
+		}
+		Some(backend) => panic!("Unknown storage backend `{backend}`"),
+	};
+
+	let twitch = Twitch::new(
+		get_secret(&secrets, "TWITCH_CLIENT_ID"),
+		get_secret(&secrets, "TWITCH_CLIENT_SECRET"),
+		get_secret(&secrets, "TWITCH_REDIRECT_URI"),
+		secrets.get("TWITCH_AUTH_URL"),
+		secrets.get("TWITCH_API_URL"),
+	);
+	let frontend_url = get_secret(&secrets, "FRONTEND_URL");
+
+	let token_key = TokenKey::new(get_secret(&secrets, "SESSION_TOKEN_KEY"));
+
+	let pool = db::init_db(database_url.clone()).await;
+	auth::hash_legacy_tokens(&pool, &token_key).await;
+	auth::spawn_session_sweeper(pool.clone());
+
+	let webhooks = Webhooks::spawn(pool.clone());
 
 	let app_state = AppState {
-		s3: aws_sdk_s3::Client::new(&s3_config),
+		storage,
+		search: SearchSync::spawn(ms.clone(), pool.clone()),
 		ms,
-		pool: db::init_db(database_url).await,
+		events: Events::spawn(pool.clone(), database_url, webhooks),
+		pool,
+		twitch: Arc::new(twitch),
+		token_key: Arc::new(token_key),
+		frontend_url: frontend_url.trim_end_matches('/').into(),
 	};
 
-	let router = Router::new()
-		.nest("/api", routes::router(&app_state))
+	let mut router = Router::new().nest("/api", routes::router(&app_state));
+
+	if let Some(root) = local_root {
+		router = router.nest_service("/cdn", ServeDir::new(root));
+	}
+
+	let router = router
 		.layer(
 			ServiceBuilder::new()
 				.layer(
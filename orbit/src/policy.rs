@@ -0,0 +1,149 @@
+//! Who may do what to whom. Handlers ask here before mutating anything that
+//! isn't strictly their own user's.
+
+use orbit_types::models::user::{EditorPermission, Role, User};
+
+use crate::db::Connection;
+use crate::error::JsonError;
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmoteAction {
+	/// Changing an emote's flags or versions.
+	Edit,
+
+	/// Only owners can delete their emotes; editors can't.
+	Delete,
+
+	/// Approval is a moderation decision, so owners can't approve their own
+	/// emotes either.
+	Approve,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetAction {
+	/// Adding and removing emotes.
+	ManageEmotes,
+	Rename,
+	ChangeCapacity,
+
+	/// Making a set the one used in its owner's channel.
+	Activate,
+
+	/// Choosing what a set inherits from is left to its owner.
+	ChangeParent,
+
+	/// Only owners can delete their sets; editors can't.
+	Delete,
+}
+
+/// How many emotes a set can hold when its owner has no role that allows more.
+pub const BASE_CAPACITY: i32 = 250;
+
+/// The largest capacity a user's sets may have, taken from their best role.
+pub fn max_capacity(roles: &[Role]) -> i32 {
+	roles
+		.iter()
+		.map(|role| match role {
+			Role::Admin | Role::Maintainer => 2000,
+			Role::Founder => 1000,
+			Role::Subscriber | Role::Contributor => 500,
+			Role::Verified | Role::Moderator => BASE_CAPACITY,
+		})
+		.max()
+		.unwrap_or(BASE_CAPACITY)
+}
+
+pub fn is_admin(user: &User) -> bool {
+	user.roles.contains(&Role::Admin)
+}
+
+/// Admins can do anything moderators can.
+pub fn is_moderator(user: &User) -> bool {
+	is_admin(user) || user.roles.contains(&Role::Moderator)
+}
+
+/// Checks that `user` may perform `action` on an emote, which has to exist.
+/// Moderators may do anything; otherwise the emote's owner and the owner's
+/// editors may edit it, and only the owner may delete it.
+pub async fn emote(conn: &Connection, user: &User, id: i64, action: EmoteAction) -> Result<()> {
+	let row = conn
+		.query_opt(
+			"
+			SELECT
+				user_id = $2 AS owner,
+				EXISTS (
+					SELECT 1
+					FROM users_to_editors
+					WHERE
+						users_to_editors.user_id = emotes.user_id
+						AND users_to_editors.editor_id = $2
+				) AS editor
+			FROM emotes
+			WHERE id = $1
+			",
+			&[&id, &user.id],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote".into()))?;
+
+	if is_moderator(user) {
+		return Ok(());
+	}
+
+	let owner: bool = row.get("owner");
+	let editor: bool = row.get("editor");
+
+	let allowed = match action {
+		EmoteAction::Edit => owner || editor,
+		EmoteAction::Delete => owner,
+		EmoteAction::Approve => false,
+	};
+
+	if allowed {
+		Ok(())
+	} else {
+		Err(JsonError::Forbidden.into())
+	}
+}
+
+/// Checks that `user` may perform `action` on a set, which has to exist. The
+/// set's owner and moderators may do anything; the owner's editors may do
+/// what their permissions allow.
+pub async fn set(conn: &Connection, user: &User, id: i64, action: SetAction) -> Result<()> {
+	let row = conn
+		.query_opt(
+			"
+			SELECT
+				sets.user_id = $2 AS owner,
+				users_to_editors.permissions
+			FROM
+				sets
+				LEFT JOIN users_to_editors ON
+					users_to_editors.user_id = sets.user_id
+					AND users_to_editors.editor_id = $2
+			WHERE sets.id = $1
+			",
+			&[&id, &user.id],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote set".into()))?;
+
+	if is_moderator(user) || row.get::<_, bool>("owner") {
+		return Ok(());
+	}
+
+	let permissions: Option<Vec<EditorPermission>> = row.get("permissions");
+
+	let required = match action {
+		SetAction::ManageEmotes | SetAction::Activate => Some(EditorPermission::ManageEmotes),
+		SetAction::Rename => Some(EditorPermission::RenameSets),
+		SetAction::ChangeCapacity => Some(EditorPermission::ChangeCapacity),
+		SetAction::ChangeParent | SetAction::Delete => None,
+	};
+
+	match (permissions, required) {
+		(Some(permissions), Some(required)) if permissions.contains(&required) => Ok(()),
+		_ => Err(JsonError::Forbidden.into()),
+	}
+}
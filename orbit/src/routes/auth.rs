@@ -0,0 +1,282 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{AppendHeaders, IntoResponse, Redirect};
+use axum::routing::{delete, get, post};
+use axum::Router;
+use chrono::Utc;
+use orbit_types::models::session::Session;
+use orbit_types::models::token::*;
+use orbit_types::Snowflake;
+use serde::Deserialize;
+
+use crate::auth::AuthSession;
+use crate::db::Conn;
+use crate::error::{Error, JsonError};
+use crate::{auth, policy, AppState, Result};
+
+const STATE_COOKIE: &str = "orbit_oauth_state";
+
+/// How long a login attempt has to make it back to the callback.
+const STATE_MAX_AGE: u32 = 10 * 60;
+
+const CHANNEL_SET_NAME: &str = "Channel Emotes";
+
+pub fn router(state: &AppState) -> Router<AppState> {
+	Router::new()
+		.route("/auth/sessions", get(get_sessions))
+		.route("/auth/sessions", delete(revoke_sessions))
+		.route("/auth/sessions/:id", delete(revoke_session))
+		.route("/auth/tokens", get(get_api_tokens))
+		.route("/auth/tokens", post(create_api_token))
+		.route("/auth/tokens/:id", delete(revoke_api_token))
+		.route_layer(axum::middleware::from_fn_with_state(
+			state.clone(),
+			auth::middleware,
+		))
+		.route("/auth/twitch", get(login))
+		.route("/auth/twitch/callback", get(callback))
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+	code: Option<String>,
+	state: Option<String>,
+	error: Option<String>,
+}
+
+/// The state is kept in a cookie so the callback can tell that it was started
+/// by this browser.
+async fn login(State(state): State<AppState>) -> impl IntoResponse {
+	let oauth_state = auth::random_token();
+
+	let cookie = format!(
+		"{STATE_COOKIE}={oauth_state}; Max-Age={STATE_MAX_AGE}; Path=/; HttpOnly; Secure; SameSite=Lax"
+	);
+
+	(
+		AppendHeaders([(header::SET_COOKIE, cookie)]),
+		Redirect::to(state.twitch.authorize_url(&oauth_state).as_str()),
+	)
+}
+
+async fn callback(
+	State(state): State<AppState>,
+	headers: HeaderMap,
+	Query(query): Query<CallbackQuery>,
+) -> Result<impl IntoResponse> {
+	let clear_cookie =
+		format!("{STATE_COOKIE}=; Max-Age=0; Path=/; HttpOnly; Secure; SameSite=Lax");
+
+	// The user declined on Twitch's side, which the frontend should show
+	// rather than an API error.
+	if let Some(mut error) = query.error {
+		if !error.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+			error = "unknown".into();
+		}
+
+		let url = format!("{}/auth/callback#error={error}", state.frontend_url);
+
+		return Ok((
+			AppendHeaders([(header::SET_COOKIE, clear_cookie)]),
+			Redirect::to(&url),
+		));
+	}
+
+	let expected = headers
+		.get_all(header::COOKIE)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(';'))
+		.find_map(|cookie| cookie.trim().strip_prefix(&format!("{STATE_COOKIE}=")));
+
+	match (expected, query.state.as_deref()) {
+		(Some(expected), Some(actual)) if !expected.is_empty() && expected == actual => (),
+		_ => return Err(JsonError::InvalidOAuthState.into()),
+	}
+
+	let code = query.code.ok_or(JsonError::MissingField("code".into()))?;
+	let twitch_user = state.twitch.user_from_code(&code).await?;
+
+	let twitch_id: i32 = twitch_user
+		.id
+		.parse()
+		.map_err(|_| Error::Twitch(format!("Unexpected user id `{}`", twitch_user.id)))?;
+
+	// Only taken once Twitch has answered, so slow responses from it don't tie
+	// up the pool.
+	let mut conn = state.pool.get().await.map_err(|_| Error::Generic)?;
+	let transaction = conn.transaction().await?;
+
+	// A new user and their channel set reference each other, so both are
+	// inserted before the user's reference is checked.
+	transaction
+		.batch_execute("SET CONSTRAINTS users_channel_set_id_fkey DEFERRED")
+		.await?;
+
+	let row = transaction
+		.query_one(
+			"
+			INSERT INTO users (id, twitch_id, username, avatar_url, channel_set_id)
+			VALUES ($1, $2, $3, $4, $5)
+			ON CONFLICT (twitch_id) DO UPDATE
+			SET
+				username = excluded.username,
+				avatar_url = excluded.avatar_url
+			RETURNING id, channel_set_id, xmax = 0 AS inserted
+			",
+			&[
+				&Snowflake::new().0,
+				&twitch_id,
+				&twitch_user.login,
+				&twitch_user.profile_image_url,
+				&Snowflake::new().0,
+			],
+		)
+		.await?;
+
+	let user_id: i64 = row.get("id");
+
+	if row.get("inserted") {
+		let channel_set_id: i64 = row.get("channel_set_id");
+
+		transaction
+			.execute(
+				"
+				INSERT INTO sets (id, name, capacity, user_id)
+				VALUES ($1, $2, $3, $4)
+				",
+				&[
+					&channel_set_id,
+					&CHANNEL_SET_NAME,
+					&policy::BASE_CAPACITY,
+					&user_id,
+				],
+			)
+			.await?;
+	}
+
+	let user_agent = headers
+		.get(header::USER_AGENT)
+		.and_then(|value| value.to_str().ok());
+	let token = auth::create_session(&transaction, &state.token_key, user_id, user_agent).await?;
+
+	transaction.commit().await?;
+
+	// A fragment never reaches the frontend's server or its logs.
+	let url = format!("{}/auth/callback#token={token}", state.frontend_url);
+
+	Ok((
+		AppendHeaders([(header::SET_COOKIE, clear_cookie)]),
+		Redirect::to(&url),
+	))
+}
+
+async fn get_sessions(Conn(conn): Conn, session: AuthSession) -> Result<Json<Vec<Session>>> {
+	let sessions = conn
+		.query(
+			"
+			SELECT
+				id, user_agent, created_at, last_used_at, expires_at,
+				id = $2 AS current
+			FROM sessions
+			WHERE user_id = $1 AND expires_at > now()
+			ORDER BY last_used_at DESC
+			",
+			&[&session.user_id, &session.id],
+		)
+		.await?
+		.into_iter()
+		.map(|row| row.into())
+		.collect();
+
+	Ok(Json(sessions))
+}
+
+/// Logs out everywhere, including the session making the request.
+async fn revoke_sessions(Conn(conn): Conn, session: AuthSession) -> Result<StatusCode> {
+	conn.execute(
+		"DELETE FROM sessions WHERE user_id = $1",
+		&[&session.user_id],
+	)
+	.await?;
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+async fn revoke_session(
+	Conn(conn): Conn,
+	session: AuthSession,
+	Path(id): Path<i64>,
+) -> Result<StatusCode> {
+	let deleted = conn
+		.execute(
+			"DELETE FROM sessions WHERE id = $1 AND user_id = $2",
+			&[&id, &session.user_id],
+		)
+		.await?;
+
+	if deleted > 0 {
+		Ok(StatusCode::NO_CONTENT)
+	} else {
+		Err(JsonError::UnknownEntity("session".into()).into())
+	}
+}
+
+async fn get_api_tokens(Conn(conn): Conn, session: AuthSession) -> Result<Json<Vec<ApiToken>>> {
+	let tokens = conn
+		.query(
+			"SELECT * FROM api_tokens WHERE user_id = $1 ORDER BY id",
+			&[&session.user_id],
+		)
+		.await?
+		.into_iter()
+		.map(|row| row.into())
+		.collect();
+
+	Ok(Json(tokens))
+}
+
+async fn create_api_token(
+	State(state): State<AppState>,
+	Conn(conn): Conn,
+	session: AuthSession,
+	Json(body): Json<CreateApiToken>,
+) -> Result<(StatusCode, Json<NewApiToken>)> {
+	if body.name.trim().is_empty() {
+		return Err(JsonError::MissingField("name".into()).into());
+	}
+
+	if body.scopes.is_empty() {
+		return Err(JsonError::MissingField("scopes".into()).into());
+	}
+
+	if body
+		.expires_at
+		.is_some_and(|expires_at| expires_at <= Utc::now())
+	{
+		return Err(JsonError::InvalidField("expires_at".into()).into());
+	}
+
+	let token = auth::create_api_token(&*conn, &state.token_key, session.user_id, &body).await?;
+
+	Ok((StatusCode::CREATED, Json(token)))
+}
+
+async fn revoke_api_token(
+	Conn(conn): Conn,
+	session: AuthSession,
+	Path(id): Path<i64>,
+) -> Result<StatusCode> {
+	let deleted = conn
+		.execute(
+			"DELETE FROM api_tokens WHERE id = $1 AND user_id = $2",
+			&[&id, &session.user_id],
+		)
+		.await?;
+
+	if deleted > 0 {
+		Ok(StatusCode::NO_CONTENT)
+	} else {
+		Err(JsonError::UnknownEntity("API token".into()).into())
+	}
+}
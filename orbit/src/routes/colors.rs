@@ -7,7 +7,7 @@ use orbit_types::Snowflake;
 use crate::auth::AuthUser;
 use crate::db::Conn;
 use crate::error::{JsonError, ResultExt};
-use crate::{AppState, Result};
+use crate::{policy, AppState, Result};
 
 pub fn router() -> Router<AppState> {
 	Router::new()
@@ -30,7 +30,9 @@ async fn create_color(
 	user: AuthUser,
 	Json(body): Json<CreateColor>,
 ) -> Result<Json<Color>> {
-	if !user.roles.contains(&Role::Admin) {
+	user.require_session()?;
+
+	if !policy::is_admin(&user) {
 		return Err(JsonError::Forbidden.into());
 	}
 
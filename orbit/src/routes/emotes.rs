@@ -1,26 +1,57 @@
-use aws_sdk_s3::types::{Delete, ObjectIdentifier};
-use axum::extract::{Json, Path, Query, State};
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Json, Multipart, Path, Query, State};
 use axum::http::StatusCode;
-use axum::routing::{delete, get, patch, post};
+use axum::routing::{delete, get, patch, post, put};
 use axum::Router;
+use meilisearch_sdk::search::Selectors;
 use orbit_types::models::emote::*;
+use orbit_types::models::event::Event;
+use orbit_types::models::token::Scope;
 use orbit_types::Snowflake;
+use serde::Deserialize;
+use tokio_postgres::types::Json as Jsonb;
 
 use crate::auth::{self, AuthUser};
 use crate::db::Conn;
 use crate::error::{Error, JsonError};
-use crate::{AppState, Result};
+use crate::policy::{self, EmoteAction};
+use crate::storage::{self, Storage};
+use crate::{search, AppState, Result};
+
+/// Large enough for the biggest image `orbit_image` accepts plus the metadata
+/// fields sent alongside it.
+const MAX_UPLOAD_SIZE: usize = 8 * 1024 * 1024;
+
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+const MAX_SEARCH_LIMIT: usize = 100;
 
 pub fn router(state: &AppState) -> Router<AppState> {
 	Router::new()
-		.route("/emotes", post(create_emote))
+		.route(
+			"/emotes",
+			post(create_emote).layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE)),
+		)
 		.route("/emotes/:id", patch(update_emote))
 		.route("/emotes/:id", delete(delete_emote))
+		.route(
+			"/emotes/:id/versions",
+			post(create_emote_version).layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE)),
+		)
+		.route(
+			"/emotes/:id/versions/:versionId/active",
+			put(set_active_emote_version),
+		)
+		.route(
+			"/emotes/:id/versions/:versionId",
+			delete(delete_emote_version),
+		)
 		.route_layer(axum::middleware::from_fn_with_state(
 			state.clone(),
 			auth::middleware,
 		))
 		.route("/emotes/:id", get(get_emote))
+		.route("/emotes/:id/versions", get(get_emote_versions))
+		.route("/emotes/:id/versions/:versionId", get(get_emote_version))
 		.route("/emotes/search", get(search_emotes))
 }
 
@@ -58,83 +89,425 @@ async fn get_emote(Conn(conn): Conn, Path(id): Path<i64>) -> Result<Json<EmoteWi
 	Ok(Json(emote))
 }
 
+/// Hits are loaded from the database rather than returned from the index so
+/// they always reflect the current state of each emote.
+///
+/// Only public, approved emotes are searched unless asked otherwise, which
+/// only moderators, or owners searching their own emotes, may do.
 async fn search_emotes(
 	State(state): State<AppState>,
+	Conn(conn): Conn,
+	user: Option<AuthUser>,
 	Query(query): Query<SearchEmotesQuery>,
-) -> Result<Json<Vec<Emote>>> {
-	let filters: Vec<&str> = query.filters.split(',').collect();
+) -> Result<Json<EmoteSearchResults>> {
+	let public = query.public.unwrap_or(true);
+	let approved = query.approved.unwrap_or(true);
+
+	if !public || !approved {
+		let allowed = user
+			.as_ref()
+			.is_some_and(|user| policy::is_moderator(user) || query.user_id == Some(user.id));
 
-	if let Some(query) = query.query {
-		todo!();
+		if !allowed {
+			return Err(JsonError::Forbidden.into());
+		}
 	}
 
-	Ok(Json(vec![]))
+	let limit = query
+		.limit
+		.unwrap_or(DEFAULT_SEARCH_LIMIT)
+		.min(MAX_SEARCH_LIMIT);
+	let offset = query.offset.unwrap_or(0);
+
+	let mut filters = [
+		("animated", query.animated),
+		("modifier", query.modifier),
+		("nsfw", query.nsfw),
+		("approved", Some(approved)),
+		("public", Some(public)),
+	]
+	.into_iter()
+	.filter_map(|(field, value)| value.map(|value| format!("{field} = {value}")))
+	.collect::<Vec<_>>();
+
+	if let Some(user_id) = query.user_id {
+		filters.push(format!("user_id = \"{user_id}\""));
+	}
+
+	let filter = filters.join(" AND ");
+	let sort = query.sort.map(|sort| match sort {
+		EmoteSort::Newest => "created_at:desc",
+		EmoteSort::Popularity => "usage:desc",
+		EmoteSort::Name => "name:asc",
+	});
+
+	let index = search::emotes(&state.ms);
+	let mut search = index.search();
+
+	search
+		.with_limit(limit)
+		.with_offset(offset)
+		.with_attributes_to_retrieve(Selectors::Some(&["id"]));
+
+	if let Some(text) = &query.query {
+		search.with_query(text);
+	}
+
+	if !filter.is_empty() {
+		search.with_filter(&filter);
+	}
+
+	let sort = sort.as_slice();
+
+	if !sort.is_empty() {
+		search.with_sort(sort);
+	}
+
+	let results = search.execute::<SearchHit>().await?;
+
+	let ids = results
+		.hits
+		.iter()
+		.filter_map(|hit| hit.result.id.parse().ok())
+		.collect::<Vec<i64>>();
+
+	// Emotes deleted since they were indexed are simply missing from the rows.
+	let mut rows = conn
+		.query(
+			"
+			SELECT
+				emotes.*,
+				(
+					SELECT COALESCE(array_agg(versions.id ORDER BY versions.id), '{}')
+					FROM versions
+					WHERE versions.emote_id = emotes.id
+				) AS versions
+			FROM emotes
+			WHERE id = ANY($1) AND public = $2 AND approved = $3
+			",
+			&[&ids, &public, &approved],
+		)
+		.await?;
+
+	rows.sort_by_key(|row| ids.iter().position(|id| *id == row.get::<_, i64>("id")));
+
+	Ok(Json(EmoteSearchResults {
+		hits: rows.into_iter().map(|row| row.into()).collect(),
+		total: results.estimated_total_hits.unwrap_or(results.hits.len()),
+		limit,
+		offset,
+	}))
+}
+
+#[derive(Deserialize)]
+struct SearchHit {
+	id: String,
 }
 
+/// Processing and storing the upload can take a while, so a database
+/// connection is only taken once that's done.
 async fn create_emote(
-	Conn(conn): Conn,
+	State(state): State<AppState>,
 	user: AuthUser,
-	Json(body): Json<CreateEmote>,
+	multipart: Multipart,
 ) -> Result<(StatusCode, Json<EmoteWithUser>)> {
-	let emote = conn
-		.query_one(
-			r#"
-			INSERT INTO
-				emotes (
-					id, name, tags, width, height, public,
-					animated, modifier, nsfw, user_id
-				)
-			VALUES
-				($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
-			RETURNING
-				*,
-				(
-					SELECT to_jsonb(users.*) AS "user"
-					FROM users
-					WHERE users.id = $10
-				)
-			"#,
-			&[
-				&Snowflake::new().0,
-				&body.name,
-				&body.tags,
-				&body.width,
-				&body.height,
-				&body.public,
-				&body.animated,
-				&body.modifier,
-				&body.nsfw,
-				&user.id,
-			],
-		)
-		.await?
-		.into();
+	user.require(Scope::EmotesWrite)?;
+
+	let (body, file) = read_upload(multipart).await?;
+
+	let id = Snowflake::new().0;
+	let version_id = Snowflake::new().0;
+
+	// The rows are only inserted once every file is stored, so an emote never
+	// points at files that don't exist.
+	let upload = store_upload(&state, &storage::version_prefix(id, version_id), file).await?;
+
+	let result = async {
+		let mut conn = state.pool.get().await.map_err(|_| Error::Generic)?;
+		let transaction = conn.transaction().await?;
+
+		let emote = transaction
+			.query_one(
+				r#"
+				INSERT INTO
+					emotes (
+						id, name, tags, width, height, public, animated,
+						modifier, nsfw, user_id, files, active_version_id
+					)
+				VALUES
+					($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+				RETURNING
+					*,
+					ARRAY[$12] AS versions,
+					(
+						SELECT to_jsonb(users.*) AS "user"
+						FROM users
+						WHERE users.id = $10
+					)
+				"#,
+				&[
+					&id,
+					&body.name,
+					&body.tags,
+					&(upload.info.width as i32),
+					&(upload.info.height as i32),
+					&body.public,
+					&upload.info.animated,
+					&body.modifier,
+					&body.nsfw,
+					&user.id,
+					&Jsonb(&upload.files),
+					&version_id,
+				],
+			)
+			.await?;
+
+		transaction
+			.execute(
+				"
+				INSERT INTO
+					versions (
+						id, name, description, emote_id,
+						width, height, animated, files
+					)
+				VALUES
+					($1, $2, '', $3, $4, $5, $6, $7)
+				",
+				&[
+					&version_id,
+					&body.name,
+					&id,
+					&(upload.info.width as i32),
+					&(upload.info.height as i32),
+					&upload.info.animated,
+					&Jsonb(&upload.files),
+				],
+			)
+			.await?;
+
+		transaction.commit().await?;
+
+		Ok::<_, Error>(emote)
+	}
+	.await;
 
-	// todo: image processing + s3
+	match result {
+		Ok(emote) => {
+			state.search.upsert(id);
 
-	Ok((StatusCode::CREATED, Json(emote)))
+			Ok((StatusCode::CREATED, Json(emote.into())))
+		}
+		Err(err) => {
+			cleanup(&state, upload.keys).await;
+			Err(err)
+		}
+	}
+}
+
+/// Reads the fields of an emote upload. Tags may be given either as repeated
+/// `tags` fields or as a single comma separated one.
+async fn read_upload(mut multipart: Multipart) -> Result<(CreateEmote, Bytes)> {
+	let mut body = CreateEmote {
+		name: String::new(),
+		tags: vec![],
+		public: false,
+		modifier: false,
+		nsfw: false,
+	};
+	let mut file = None;
+
+	while let Some(field) = multipart.next_field().await? {
+		let Some(name) = field.name().map(str::to_owned) else {
+			continue;
+		};
+
+		match name.as_str() {
+			"file" => file = Some(field.bytes().await?),
+			"name" => body.name = field.text().await?.trim().to_owned(),
+			"tags" => body.tags.extend(
+				field
+					.text()
+					.await?
+					.split(',')
+					.map(str::trim)
+					.filter(|tag| !tag.is_empty())
+					.map(str::to_owned),
+			),
+			"public" | "modifier" | "nsfw" => {
+				let value = field
+					.text()
+					.await?
+					.parse()
+					.map_err(|_| JsonError::InvalidField(name.clone()))?;
+
+				match name.as_str() {
+					"public" => body.public = value,
+					"modifier" => body.modifier = value,
+					_ => body.nsfw = value,
+				}
+			}
+			_ => (),
+		}
+	}
+
+	if body.name.is_empty() {
+		return Err(JsonError::MissingField("name".into()).into());
+	}
+
+	let file = file.ok_or(JsonError::MissingField("file".into()))?;
+
+	Ok((body, file))
+}
+
+struct Upload {
+	info: orbit_image::ImageInfo,
+	files: Vec<EmoteFile>,
+	keys: Vec<String>,
+}
+
+/// Decodes an uploaded image, generates its renditions and stores them under
+/// `prefix`. If any file fails to store, the ones already stored are removed.
+async fn store_upload(state: &AppState, prefix: &str, file: Bytes) -> Result<Upload> {
+	let (info, files) = tokio::task::spawn_blocking(move || {
+		let image = orbit_image::decode(&file)?;
+		let files = orbit_image::process(&image)?;
+
+		Ok::<_, orbit_image::Error>((image.info, files))
+	})
+	.await
+	.map_err(|_| Error::Generic)??;
+
+	let emote_files = emote_files(state.storage.as_ref(), prefix, &files);
+	let mut keys = vec![];
+
+	for file in files {
+		let key = format!("{prefix}{}", file.name());
+
+		if let Err(err) = state
+			.storage
+			.put(&key, file.bytes, file.format.mime_type())
+			.await
+		{
+			cleanup(state, keys).await;
+			return Err(err);
+		}
+
+		keys.push(key);
+	}
+
+	Ok(Upload {
+		info,
+		files: emote_files,
+		keys,
+	})
+}
+
+async fn cleanup(state: &AppState, keys: Vec<String>) {
+	if let Err(err) = state.storage.delete(&keys).await {
+		tracing::error!(?err, "Failed to clean up emote files");
+	}
+}
+
+fn emote_files(storage: &dyn Storage, prefix: &str, files: &[orbit_image::File]) -> Vec<EmoteFile> {
+	let url = |file: &orbit_image::File| storage.url(&format!("{prefix}{}", file.name()));
+
+	files
+		.iter()
+		.filter(|file| !file.is_static)
+		.map(|file| {
+			// Stills of animated GIFs are stored as PNG rather than as a
+			// single-frame GIF.
+			let still_format = match file.format {
+				orbit_image::ImageFormat::Gif => orbit_image::ImageFormat::Png,
+				format => format,
+			};
+
+			let still = files.iter().find(|other| {
+				other.is_static && other.scale == file.scale && other.format == still_format
+			});
+
+			EmoteFile {
+				name: file.name(),
+				format: file.format.extension().into(),
+				width: file.width as i32,
+				height: file.height as i32,
+				size: file.bytes.len() as i64,
+				url: url(file),
+				static_url: still.map(url),
+			}
+		})
+		.collect()
 }
 
 async fn update_emote(
+	State(state): State<AppState>,
 	Conn(conn): Conn,
+	user: AuthUser,
 	Path(id): Path<i64>,
 	Json(body): Json<UpdateEmote>,
 ) -> Result<Json<Emote>> {
-	let emote = conn
+	user.require(Scope::EmotesWrite)?;
+
+	policy::emote(&conn, &user, id, EmoteAction::Edit).await?;
+
+	if body.approved.is_some() {
+		policy::emote(&conn, &user, id, EmoteAction::Approve).await?;
+	}
+
+	// The previous approval is read under the row's lock, so only a request that
+	// actually changes it reports the change.
+	let row = conn
 		.query_opt(
 			"
 			UPDATE emotes
 			SET
-				approved = COALESCE($1, approved),
-				nsfw = COALESCE($2, nsfw)
-			WHERE id = $3
-			RETURNING *
+				approved = COALESCE($1, emotes.approved),
+				nsfw = COALESCE($2, emotes.nsfw)
+			FROM (
+				SELECT approved FROM emotes WHERE id = $3 FOR UPDATE
+			) AS previous
+			WHERE emotes.id = $3
+			RETURNING
+				emotes.*,
+				previous.approved AS was_approved,
+				(
+					SELECT COALESCE(array_agg(id ORDER BY id), '{}')
+					FROM versions
+					WHERE emote_id = emotes.id
+				) AS versions,
+				(
+					SELECT COALESCE(array_agg(set_id), '{}')
+					FROM emotes_to_sets
+					WHERE emote_id = emotes.id
+				) AS set_ids
 			",
 			&[&body.approved, &body.nsfw, &id],
 		)
 		.await?
-		.ok_or(JsonError::UnknownEntity("emote".into()))?
-		.into();
+		.ok_or(JsonError::UnknownEntity("emote".into()))?;
+
+	let user_id: i64 = row.get("user_id");
+
+	state.search.upsert(id);
+	state.events.publish(Event::EmoteUpdated {
+		emote_id: id,
+		user_id,
+		set_ids: row.get("set_ids"),
+	});
+
+	match (row.get("was_approved"), body.approved) {
+		(false, Some(true)) => state.events.publish(Event::EmoteApproved {
+			emote_id: id,
+			user_id,
+		}),
+		(true, Some(false)) => state.events.publish(Event::EmoteRejected {
+			emote_id: id,
+			user_id,
+		}),
+		_ => (),
+	}
+
+	let emote = row.into();
 
 	Ok(Json(emote))
 }
@@ -142,62 +515,313 @@ async fn update_emote(
 async fn delete_emote(
 	State(state): State<AppState>,
 	Conn(conn): Conn,
+	user: AuthUser,
+	Path(id): Path<i64>,
+) -> Result<StatusCode> {
+	user.require(Scope::EmotesWrite)?;
+
+	policy::emote(&conn, &user, id, EmoteAction::Delete).await?;
+
+	// The sets are read before the delete cascades to them.
+	let row = conn
+		.query_opt(
+			"
+			DELETE FROM emotes
+			WHERE id = $1
+			RETURNING
+				user_id,
+				(
+					SELECT COALESCE(array_agg(set_id), '{}')
+					FROM emotes_to_sets
+					WHERE emote_id = emotes.id
+				) AS set_ids
+			",
+			&[&id],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote".into()))?;
+
+	state.search.delete(id);
+	state.events.publish(Event::EmoteDeleted {
+		emote_id: id,
+		user_id: row.get("user_id"),
+		set_ids: row.get("set_ids"),
+	});
+
+	// The emote is already gone at this point, so failing to remove its files
+	// only leaves orphaned objects behind and shouldn't fail the request.
+	if let Err(err) = state
+		.storage
+		.delete_prefix(&storage::emote_prefix(id))
+		.await
+	{
+		tracing::error!(?err, emote_id = id, "Failed to delete emote files");
+	}
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_emote_versions(
+	Conn(conn): Conn,
+	Path(id): Path<i64>,
+) -> Result<Json<Vec<EmoteVersion>>> {
+	let rows = conn
+		.query(
+			"
+			SELECT
+				versions.*,
+				COALESCE(versions.id = emotes.active_version_id, false) AS active
+			FROM
+				emotes
+				LEFT JOIN versions ON emotes.id = versions.emote_id
+			WHERE emotes.id = $1
+			ORDER BY versions.id
+			",
+			&[&id],
+		)
+		.await?;
+
+	if rows.is_empty() {
+		return Err(JsonError::UnknownEntity("emote".into()).into());
+	}
+
+	let versions = rows
+		.into_iter()
+		.filter(|row| row.get::<_, Option<i64>>("id").is_some())
+		.map(|row| row.into())
+		.collect();
+
+	Ok(Json(versions))
+}
+
+async fn get_emote_version(
+	Conn(conn): Conn,
+	Path((id, version_id)): Path<(i64, i64)>,
+) -> Result<Json<EmoteVersion>> {
+	let version = conn
+		.query_opt(
+			"
+			SELECT
+				versions.*,
+				COALESCE(versions.id = emotes.active_version_id, false) AS active
+			FROM
+				versions
+				JOIN emotes ON emotes.id = versions.emote_id
+			WHERE versions.id = $1 AND versions.emote_id = $2
+			",
+			&[&version_id, &id],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote version".into()))?
+		.into();
+
+	Ok(Json(version))
+}
+
+/// Like [`create_emote`], no connection is held while the upload is processed.
+async fn create_emote_version(
+	State(state): State<AppState>,
+	user: AuthUser,
 	Path(id): Path<i64>,
+	multipart: Multipart,
+) -> Result<(StatusCode, Json<EmoteVersion>)> {
+	user.require(Scope::EmotesWrite)?;
+
+	let (body, file) = read_version_upload(multipart).await?;
+
+	{
+		let conn = state.pool.get_owned().await.map_err(|_| Error::Generic)?;
+
+		policy::emote(&conn, &user, id, EmoteAction::Edit).await?;
+	}
+
+	let version_id = Snowflake::new().0;
+	let upload = store_upload(&state, &storage::version_prefix(id, version_id), file).await?;
+
+	let version = async {
+		let conn = state.pool.get().await.map_err(|_| Error::Generic)?;
+
+		let version = conn
+			.query_one(
+				"
+				INSERT INTO
+					versions (
+						id, name, description, emote_id,
+						width, height, animated, files
+					)
+				VALUES
+					($1, $2, $3, $4, $5, $6, $7, $8)
+				RETURNING *, false AS active
+				",
+				&[
+					&version_id,
+					&body.name,
+					&body.description,
+					&id,
+					&(upload.info.width as i32),
+					&(upload.info.height as i32),
+					&upload.info.animated,
+					&Jsonb(&upload.files),
+				],
+			)
+			.await?;
+
+		Ok::<_, Error>(version)
+	}
+	.await;
+
+	match version {
+		Ok(version) => Ok((StatusCode::CREATED, Json(version.into()))),
+		Err(err) => {
+			cleanup(&state, upload.keys).await;
+			Err(err)
+		}
+	}
+}
+
+async fn read_version_upload(mut multipart: Multipart) -> Result<(CreateEmoteVersion, Bytes)> {
+	let mut body = CreateEmoteVersion {
+		name: String::new(),
+		description: String::new(),
+	};
+	let mut file = None;
+
+	while let Some(field) = multipart.next_field().await? {
+		match field.name() {
+			Some("file") => file = Some(field.bytes().await?),
+			Some("name") => body.name = field.text().await?.trim().to_owned(),
+			Some("description") => body.description = field.text().await?.trim().to_owned(),
+			_ => (),
+		}
+	}
+
+	if body.name.is_empty() {
+		return Err(JsonError::MissingField("name".into()).into());
+	}
+
+	let file = file.ok_or(JsonError::MissingField("file".into()))?;
+
+	Ok((body, file))
+}
+
+async fn set_active_emote_version(
+	State(state): State<AppState>,
+	Conn(conn): Conn,
+	user: AuthUser,
+	Path((id, version_id)): Path<(i64, i64)>,
+) -> Result<Json<Emote>> {
+	user.require(Scope::EmotesWrite)?;
+
+	policy::emote(&conn, &user, id, EmoteAction::Edit).await?;
+
+	let row = conn
+		.query_opt(
+			"
+			UPDATE emotes
+			SET
+				active_version_id = versions.id,
+				width = versions.width,
+				height = versions.height,
+				animated = versions.animated,
+				files = versions.files
+			FROM versions
+			WHERE
+				emotes.id = $1
+				AND versions.id = $2
+				AND versions.emote_id = emotes.id
+			RETURNING
+				emotes.*,
+				(
+					SELECT COALESCE(array_agg(v.id ORDER BY v.id), '{}')
+					FROM versions AS v
+					WHERE v.emote_id = emotes.id
+				) AS versions,
+				(
+					SELECT COALESCE(array_agg(set_id), '{}')
+					FROM emotes_to_sets
+					WHERE emote_id = emotes.id
+				) AS set_ids
+			",
+			&[&id, &version_id],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote version".into()))?;
+
+	state.search.upsert(id);
+	state.events.publish(Event::EmoteUpdated {
+		emote_id: id,
+		user_id: row.get("user_id"),
+		set_ids: row.get("set_ids"),
+	});
+
+	let emote = row.into();
+
+	Ok(Json(emote))
+}
+
+async fn delete_emote_version(
+	State(state): State<AppState>,
+	Conn(conn): Conn,
+	user: AuthUser,
+	Path((id, version_id)): Path<(i64, i64)>,
 ) -> Result<StatusCode> {
+	user.require(Scope::EmotesWrite)?;
+
+	policy::emote(&conn, &user, id, EmoteAction::Edit).await?;
+
+	let active: Option<bool> = conn
+		.query_opt(
+			"
+			SELECT COALESCE(emotes.active_version_id = versions.id, false)
+			FROM
+				versions
+				JOIN emotes ON emotes.id = versions.emote_id
+			WHERE versions.id = $1 AND versions.emote_id = $2
+			",
+			&[&version_id, &id],
+		)
+		.await?
+		.map(|row| row.get(0));
+
+	match active {
+		None => return Err(JsonError::UnknownEntity("emote version".into()).into()),
+		Some(true) => return Err(JsonError::ActiveVersion.into()),
+		Some(false) => (),
+	}
+
 	let deleted: bool = conn
 		.query_one(
 			"
 			WITH returned AS (
-				DELETE FROM emotes
-				WHERE id = $1
+				DELETE FROM versions
+				USING emotes
+				WHERE
+					versions.id = $1
+					AND versions.emote_id = $2
+					AND emotes.id = versions.emote_id
+					AND emotes.active_version_id IS DISTINCT FROM versions.id
 				RETURNING 1
 			)
 			SELECT EXISTS (
 				SELECT 1 FROM returned
 			)
 			",
-			&[&id],
+			&[&version_id, &id],
 		)
 		.await?
 		.get(0);
 
-	let mut to_delete: Vec<ObjectIdentifier> = vec![];
-	let response = state
-		.s3
-		.list_objects_v2()
-		.bucket("cdn.withorbit.xyz")
-		.send()
-		.await
-		.map_err(|_| Error::Cdn)?;
-
-	for object in response.contents.unwrap_or_default().iter() {
-		if let Some(key) = &object.key {
-			to_delete.push(
-				ObjectIdentifier::builder()
-					.set_key(Some(key.into()))
-					.build()
-					.map_err(|_| Error::Cdn)?,
-			)
-		}
+	if !deleted {
+		return Err(JsonError::ActiveVersion.into());
 	}
 
-	state
-		.s3
-		.delete_objects()
-		.bucket("cdn.withorbit.xyz")
-		.delete(
-			Delete::builder()
-				.set_objects(Some(to_delete))
-				.build()
-				.map_err(|_| Error::Cdn)?,
-		)
-		.send()
+	if let Err(err) = state
+		.storage
+		.delete_prefix(&storage::version_prefix(id, version_id))
 		.await
-		.map_err(|_| Error::Cdn)?;
-
-	if deleted {
-		Ok(StatusCode::NO_CONTENT)
-	} else {
-		Err(JsonError::UnknownEntity("emote".into()).into())
+	{
+		tracing::error!(?err, version_id, "Failed to delete emote version files");
 	}
+
+	Ok(StatusCode::NO_CONTENT)
 }
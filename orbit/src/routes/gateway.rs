@@ -0,0 +1,454 @@
+//! Pushes events to clients so they don't have to poll, over a WebSocket or,
+//! where those aren't available, Server-Sent Events.
+//!
+//! Clients subscribe to channels, sets and users and are sent every event that
+//! concerns one of them. Each event carries a sequence number; after
+//! reconnecting, a client resumes with the last number it saw and is sent
+//! whatever it missed.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use orbit_types::models::event::Event;
+use orbit_types::models::gateway::*;
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+use crate::db::Pool;
+use crate::error::{Error, JsonError};
+use crate::events::{Dispatch, Events};
+use crate::{AppState, Result};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How late a heartbeat can be before the connection is dropped, to allow for
+/// slow networks.
+const HEARTBEAT_GRACE: Duration = Duration::from_secs(10);
+
+const MAX_SUBSCRIPTIONS: usize = 100;
+const MAX_MESSAGE_SIZE: usize = 16 * 1024;
+
+const CLOSE_HEARTBEAT_TIMEOUT: u16 = 4000;
+const CLOSE_INVALID_SESSION: u16 = 4001;
+
+pub fn router() -> Router<AppState> {
+	Router::new()
+		.route("/gateway", get(gateway))
+		.route("/gateway/events", get(event_stream))
+}
+
+async fn gateway(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
+	ws.max_message_size(MAX_MESSAGE_SIZE)
+		.on_upgrade(move |socket| async move {
+			let mut connection = Connection {
+				socket,
+				state,
+				filter: Filter::default(),
+				seq: 0,
+			};
+
+			connection.run().await;
+		})
+}
+
+/// Decides which events a client gets, shared by both transports.
+#[derive(Default)]
+struct Filter {
+	subscriptions: HashSet<Subscription>,
+
+	/// The set each subscribed channel currently has active, kept up to date
+	/// from the channel's own events.
+	channel_sets: HashMap<i64, i64>,
+}
+
+impl Filter {
+	async fn subscribe(&mut self, pool: &Pool, subscription: Subscription) -> Result<()> {
+		if self.subscriptions.contains(&subscription) {
+			return Ok(());
+		}
+
+		if self.subscriptions.len() >= MAX_SUBSCRIPTIONS {
+			return Err(JsonError::TooManySubscriptions(MAX_SUBSCRIPTIONS).into());
+		}
+
+		if let Subscription::Channel(user_id) = subscription {
+			let conn = pool.get().await.map_err(|_| Error::Generic)?;
+
+			let set_id: i64 = conn
+				.query_opt(
+					"SELECT channel_set_id FROM users WHERE id = $1",
+					&[&user_id],
+				)
+				.await?
+				.ok_or(JsonError::UnknownEntity("user".into()))?
+				.get(0);
+
+			self.channel_sets.insert(user_id, set_id);
+		}
+
+		self.subscriptions.insert(subscription);
+
+		Ok(())
+	}
+
+	fn unsubscribe(&mut self, subscription: Subscription) {
+		self.subscriptions.remove(&subscription);
+
+		if let Subscription::Channel(user_id) = subscription {
+			self.channel_sets.remove(&user_id);
+		}
+	}
+
+	/// Has to see every event in order, including unwanted ones, to keep
+	/// track of channel set switches.
+	fn wants(&mut self, dispatch: &Dispatch) -> bool {
+		let event = &dispatch.event;
+
+		if let Event::ChannelSetUpdated { user_id, set_id } = *event {
+			if let Some(channel_set) = self.channel_sets.get_mut(&user_id) {
+				*channel_set = set_id;
+			}
+		}
+
+		match event {
+			Event::SetEmoteAdded { .. }
+			| Event::SetEmoteRemoved { .. }
+			| Event::SetEmoteRenamed { .. } => self.wants_sets(&dispatch.set_ids),
+			Event::EmoteApproved { user_id, .. } | Event::EmoteRejected { user_id, .. } => {
+				self.subscriptions.contains(&Subscription::User(*user_id))
+			}
+			Event::ChannelSetUpdated { user_id, .. } => {
+				self.subscriptions
+					.contains(&Subscription::Channel(*user_id))
+					|| self.subscriptions.contains(&Subscription::User(*user_id))
+			}
+			Event::EmoteUpdated { user_id, .. } | Event::EmoteDeleted { user_id, .. } => {
+				self.subscriptions.contains(&Subscription::User(*user_id))
+					|| self.wants_sets(&dispatch.set_ids)
+			}
+		}
+	}
+
+	/// Sets inheriting a change count too, so a channel whose set inherits
+	/// from another hears about that set's emotes.
+	fn wants_sets(&self, set_ids: &[i64]) -> bool {
+		set_ids.iter().any(|&set_id| {
+			self.subscriptions.contains(&Subscription::Set(set_id))
+				|| self
+					.channel_sets
+					.values()
+					.any(|&channel_set| channel_set == set_id)
+		})
+	}
+}
+
+struct Connection {
+	socket: WebSocket,
+	state: AppState,
+	filter: Filter,
+
+	/// The newest event this connection has been sent.
+	seq: u64,
+}
+
+/// Why a connection should stop.
+enum Close {
+	/// The client went away or the socket broke.
+	Disconnected,
+	Code(u16, &'static str),
+}
+
+impl From<axum::Error> for Close {
+	fn from(_: axum::Error) -> Self {
+		Self::Disconnected
+	}
+}
+
+impl Connection {
+	async fn run(&mut self) {
+		// Subscribing before anything else means no event can fall between the
+		// sequence number sent below and the first one received.
+		let mut events = self.state.events.subscribe();
+		self.seq = self.state.events.last_seq();
+
+		let hello = ServerMessage::Hello {
+			heartbeat_interval: HEARTBEAT_INTERVAL.as_millis() as u64,
+			seq: self.seq,
+		};
+
+		if self.send(&hello).await.is_err() {
+			return;
+		}
+
+		let mut deadline = Instant::now() + HEARTBEAT_INTERVAL + HEARTBEAT_GRACE;
+
+		let close = loop {
+			let result = tokio::select! {
+				message = self.socket.recv() => match message {
+					Some(Ok(Message::Text(text))) => self.handle(&text, &mut deadline).await,
+					Some(Ok(Message::Close(_))) | None => Err(Close::Disconnected),
+					Some(Ok(_)) => Ok(()),
+					Some(Err(err)) => {
+						tracing::debug!(?err, "Gateway socket error");
+						Err(Close::Disconnected)
+					}
+				},
+				received = events.recv() => match received {
+					Ok(dispatch) => self.dispatch(&dispatch).await,
+					Err(broadcast::error::RecvError::Lagged(_)) => self.replay().await,
+					Err(broadcast::error::RecvError::Closed) => Err(Close::Disconnected),
+				},
+				_ = tokio::time::sleep_until(deadline) => {
+					Err(Close::Code(CLOSE_HEARTBEAT_TIMEOUT, "Heartbeat timed out"))
+				}
+			};
+
+			if let Err(close) = result {
+				break close;
+			}
+		};
+
+		if let Close::Code(code, reason) = close {
+			let frame = CloseFrame {
+				code,
+				reason: Cow::Borrowed(reason),
+			};
+
+			// The connection is going away either way.
+			let _ = self.socket.send(Message::Close(Some(frame))).await;
+		}
+	}
+
+	async fn handle(&mut self, text: &str, deadline: &mut Instant) -> Result<(), Close> {
+		let message = match serde_json::from_str(text) {
+			Ok(message) => message,
+			Err(err) => return self.error(format!("Invalid message: {err}")).await,
+		};
+
+		match message {
+			ClientMessage::Heartbeat => {
+				*deadline = Instant::now() + HEARTBEAT_INTERVAL + HEARTBEAT_GRACE;
+				self.send(&ServerMessage::HeartbeatAck).await?;
+			}
+			ClientMessage::Subscribe(subscription) => {
+				match self.filter.subscribe(&self.state.pool, subscription).await {
+					Ok(()) => self.send(&ServerMessage::Subscribed(subscription)).await?,
+					Err(err) => self.error(err.to_string()).await?,
+				}
+			}
+			ClientMessage::Unsubscribe(subscription) => {
+				self.filter.unsubscribe(subscription);
+				self.send(&ServerMessage::Unsubscribed(subscription))
+					.await?;
+			}
+			ClientMessage::Resume { seq, subscriptions } => {
+				for subscription in subscriptions {
+					if let Err(err) = self.filter.subscribe(&self.state.pool, subscription).await {
+						return self.error(err.to_string()).await;
+					}
+				}
+
+				self.seq = seq;
+				self.replay().await?;
+				self.send(&ServerMessage::Resumed).await?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Sends everything after the last event this connection was sent, or
+	/// gives up on the connection if some of it is gone.
+	async fn replay(&mut self) -> Result<(), Close> {
+		let Some(missed) = self.state.events.since(self.seq) else {
+			self.send(&ServerMessage::InvalidSession).await?;
+
+			return Err(Close::Code(CLOSE_INVALID_SESSION, "Missed events"));
+		};
+
+		for dispatch in missed {
+			self.dispatch(&dispatch).await?;
+		}
+
+		Ok(())
+	}
+
+	async fn dispatch(&mut self, dispatch: &Arc<Dispatch>) -> Result<(), Close> {
+		// A replay can overlap with events that were already queued.
+		if dispatch.seq <= self.seq {
+			return Ok(());
+		}
+
+		self.seq = dispatch.seq;
+
+		if !self.filter.wants(dispatch) {
+			return Ok(());
+		}
+
+		self.send(&ServerMessage::Dispatch {
+			seq: dispatch.seq,
+			event: dispatch.event.clone(),
+		})
+		.await
+	}
+
+	async fn error(&mut self, message: String) -> Result<(), Close> {
+		self.send(&ServerMessage::Error { message }).await
+	}
+
+	async fn send(&mut self, message: &ServerMessage) -> Result<(), Close> {
+		let text = serde_json::to_string(message).expect("Gateway messages always serialize");
+
+		self.socket.send(Message::Text(text)).await?;
+
+		Ok(())
+	}
+}
+
+/// Comma-separated ids to subscribe to, since an `EventSource` can't send
+/// anything after connecting.
+#[derive(Deserialize)]
+struct EventStreamQuery {
+	channels: Option<String>,
+	sets: Option<String>,
+	users: Option<String>,
+}
+
+/// The Server-Sent Events version of the gateway. Browsers reconnect on their
+/// own and send the id of the last event they got as `Last-Event-ID`, which is
+/// used to replay what was missed.
+async fn event_stream(
+	State(state): State<AppState>,
+	headers: HeaderMap,
+	Query(query): Query<EventStreamQuery>,
+) -> Result<impl IntoResponse> {
+	let mut filter = Filter::default();
+
+	let lists = [
+		(
+			query.channels,
+			"channels",
+			Subscription::Channel as fn(i64) -> _,
+		),
+		(query.sets, "sets", Subscription::Set),
+		(query.users, "users", Subscription::User),
+	];
+
+	for (list, field, subscription) in lists {
+		for id in list.iter().flat_map(|list| list.split(',')) {
+			let id = id
+				.trim()
+				.parse()
+				.map_err(|_| JsonError::InvalidField(field.into()))?;
+
+			filter.subscribe(&state.pool, subscription(id)).await?;
+		}
+	}
+
+	let last_event_id = headers
+		.get("Last-Event-ID")
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.parse().ok());
+
+	let mut stream = EventStream {
+		receiver: state.events.subscribe(),
+		events: state.events.clone(),
+		filter,
+		seq: state.events.last_seq(),
+		pending: VecDeque::new(),
+		invalid: false,
+	};
+
+	if let Some(seq) = last_event_id {
+		stream.resume(seq);
+	}
+
+	let stream = futures_util::stream::unfold(stream, |mut stream| async move {
+		let event = stream.next().await?;
+
+		Some((event, stream))
+	});
+
+	Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+struct EventStream {
+	receiver: broadcast::Receiver<Arc<Dispatch>>,
+	events: Events,
+	filter: Filter,
+
+	/// The newest event the stream has got to, sent or not.
+	seq: u64,
+
+	/// Missed events waiting to be replayed.
+	pending: VecDeque<Arc<Dispatch>>,
+
+	/// Set when events were missed that can't be replayed any more.
+	invalid: bool,
+}
+
+impl EventStream {
+	fn resume(&mut self, seq: u64) {
+		match self.events.since(seq) {
+			Some(missed) => {
+				self.seq = seq;
+				self.pending.extend(missed);
+			}
+			None => self.invalid = true,
+		}
+	}
+
+	async fn next(&mut self) -> Option<Result<SseEvent, axum::Error>> {
+		loop {
+			// The stream carries on from the newest event rather than closing,
+			// and the id stops the browser from resuming from before the gap.
+			if self.invalid {
+				self.invalid = false;
+				self.pending.clear();
+				self.seq = self.events.last_seq();
+
+				return Some(Ok(SseEvent::default()
+					.event("invalid_session")
+					.id(self.seq.to_string())
+					.data("")));
+			}
+
+			let dispatch = match self.pending.pop_front() {
+				Some(dispatch) => dispatch,
+				None => match self.receiver.recv().await {
+					Ok(dispatch) => dispatch,
+					Err(broadcast::error::RecvError::Lagged(_)) => {
+						self.resume(self.seq);
+						continue;
+					}
+					Err(broadcast::error::RecvError::Closed) => return None,
+				},
+			};
+
+			// A replay can overlap with events that were already queued.
+			if dispatch.seq <= self.seq {
+				continue;
+			}
+
+			self.seq = dispatch.seq;
+
+			if self.filter.wants(&dispatch) {
+				return Some(
+					SseEvent::default()
+						.id(dispatch.seq.to_string())
+						.json_data(&dispatch.event),
+				);
+			}
+		}
+	}
+}
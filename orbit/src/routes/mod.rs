@@ -3,16 +3,22 @@ use axum::Router;
 use crate::AppState;
 
 pub mod admin;
+pub mod auth;
 pub mod colors;
 pub mod emotes;
+pub mod gateway;
 pub mod sets;
 pub mod users;
+pub mod webhooks;
 
 pub fn router(state: &AppState) -> Router<AppState> {
 	Router::new()
 		.merge(self::admin::router())
+		.merge(self::auth::router(state))
 		.merge(self::colors::router())
 		.merge(self::emotes::router(state))
+		.merge(self::gateway::router())
 		.merge(self::sets::router(state))
 		.merge(self::users::router(state))
+		.merge(self::webhooks::router(state))
 }
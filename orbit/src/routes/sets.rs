@@ -1,15 +1,30 @@
-use axum::extract::{Json, Path};
+use axum::extract::{Json, Path, Query, State};
 use axum::http::StatusCode;
 use axum::routing::{delete, get, patch, post, put};
 use axum::Router;
+use orbit_types::models::event::Event;
 use orbit_types::models::set::*;
+use orbit_types::models::token::Scope;
+use orbit_types::models::user::Role;
 use orbit_types::Snowflake;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Transaction;
 
 use crate::auth::{self, AuthUser};
 use crate::db::Conn;
 use crate::error::{JsonError, ResultExt};
+use crate::policy::{self, SetAction};
 use crate::{AppState, Result};
 
+const MAX_ALIAS_LENGTH: usize = 100;
+
+/// How many sets can sit above a set, so resolving one stays cheap.
+pub const MAX_INHERITANCE_DEPTH: i32 = 3;
+
+/// Held while changing a set's parent so two concurrent changes can't form a
+/// cycle between them.
+const PARENT_LOCK: i64 = 0x6f72_6269_7470;
+
 pub fn router(state: &AppState) -> Router<AppState> {
 	Router::new()
 		.route("/sets", post(create_set))
@@ -17,31 +32,38 @@ pub fn router(state: &AppState) -> Router<AppState> {
 		.route("/sets/:id", delete(delete_set))
 		.route("/sets/:id/emotes/:emoteId", put(add_set_emote))
 		.route("/sets/:id/emotes/:emoteId", delete(remove_set_emote))
+		.route("/sets/:id/emotes/:emoteId/alias", put(set_emote_alias))
+		.route("/sets/:id/emotes/:emoteId/alias", delete(clear_emote_alias))
+		.route("/sets/:id/exclusions/:emoteId", put(add_set_exclusion))
+		.route(
+			"/sets/:id/exclusions/:emoteId",
+			delete(remove_set_exclusion),
+		)
+		.route("/sets/:id/parent/:parentId", put(set_set_parent))
+		.route("/sets/:id/parent", delete(clear_set_parent))
+		.route("/sets/:id/fork", post(fork_set))
 		.route_layer(axum::middleware::from_fn_with_state(
 			state.clone(),
 			auth::middleware,
 		))
 		.route("/sets/:id", get(get_set))
+		.route("/sets/:id/forks", get(get_set_forks))
 }
 
-async fn get_set(Conn(conn): Conn, Path(id): Path<i64>) -> Result<Json<EmoteSetWithEmotes>> {
+async fn get_set(
+	Conn(conn): Conn,
+	Path(id): Path<i64>,
+	Query(query): Query<GetEmoteSetQuery>,
+) -> Result<Json<EmoteSetWithEmotes>> {
+	let params: [&(dyn ToSql + Sync); 2] = [&id, &MAX_INHERITANCE_DEPTH];
+	let (sql, params) = if query.resolved {
+		(RESOLVED_SET_QUERY, &params[..])
+	} else {
+		(SET_QUERY, &params[..1])
+	};
+
 	let set = conn
-		.query_opt(
-			"
-			SELECT
-				sets.*,
-				COALESCE(
-					jsonb_agg(emotes.*) FILTER (WHERE emotes.id IS NOT NULL), '[]'
-				) AS emotes
-			FROM
-				sets
-				LEFT JOIN emotes_to_sets AS m2m ON sets.id = m2m.set_id
-				LEFT JOIN emotes ON m2m.emote_id = emotes.id
-			WHERE sets.id = $1
-			GROUP BY sets.id
-			",
-			&[&id],
-		)
+		.query_opt(sql, params)
 		.await?
 		.ok_or(JsonError::UnknownEntity("emote set".into()))?
 		.into();
@@ -49,11 +71,111 @@ async fn get_set(Conn(conn): Conn, Path(id): Path<i64>) -> Result<Json<EmoteSetW
 	Ok(Json(set))
 }
 
+const SET_QUERY: &str = "
+	SELECT
+		sets.*,
+		COALESCE(
+			jsonb_agg(
+				to_jsonb(emotes.*) || jsonb_build_object(
+					'versions',
+					(
+						SELECT COALESCE(jsonb_agg(versions.id ORDER BY versions.id), '[]')
+						FROM versions
+						WHERE versions.emote_id = emotes.id
+					),
+					'alias',
+					m2m.alias
+				)
+			) FILTER (WHERE emotes.id IS NOT NULL),
+			'[]'
+		) AS emotes
+	FROM
+		sets
+		LEFT JOIN emotes_to_sets AS m2m ON sets.id = m2m.set_id
+		LEFT JOIN emotes ON m2m.emote_id = emotes.id
+	WHERE sets.id = $1
+	GROUP BY sets.id
+";
+
+/// Walks up from the set through its parents. An emote is kept from the
+/// closest set that has it unless a closer set excludes it, and where two
+/// emotes end up with the same name the closer one overrides the other.
+const RESOLVED_SET_QUERY: &str = "
+	WITH RECURSIVE chain AS (
+		SELECT id, parent_id, 0 AS depth
+		FROM sets
+		WHERE id = $1
+
+		UNION ALL
+
+		SELECT sets.id, sets.parent_id, chain.depth + 1
+		FROM
+			sets
+			JOIN chain ON sets.id = chain.parent_id
+		WHERE chain.depth < $2
+	),
+	entries AS (
+		SELECT DISTINCT ON (m2m.emote_id)
+			m2m.emote_id, m2m.alias, chain.id AS set_id, chain.depth
+		FROM
+			chain
+			JOIN emotes_to_sets AS m2m ON m2m.set_id = chain.id
+		WHERE NOT EXISTS (
+			SELECT 1
+			FROM
+				set_exclusions AS exclusion
+				JOIN chain AS closer ON closer.id = exclusion.set_id
+			WHERE
+				exclusion.emote_id = m2m.emote_id
+				AND closer.depth < chain.depth
+		)
+		ORDER BY m2m.emote_id, chain.depth
+	),
+	named AS (
+		SELECT DISTINCT ON (COALESCE(entries.alias, emotes.name))
+			entries.*
+		FROM
+			entries
+			JOIN emotes ON emotes.id = entries.emote_id
+		ORDER BY COALESCE(entries.alias, emotes.name), entries.depth
+	)
+	SELECT
+		sets.*,
+		(
+			SELECT COALESCE(
+				jsonb_agg(
+					to_jsonb(emotes.*) || jsonb_build_object(
+						'versions',
+						(
+							SELECT COALESCE(jsonb_agg(versions.id ORDER BY versions.id), '[]')
+							FROM versions
+							WHERE versions.emote_id = emotes.id
+						),
+						'alias',
+						named.alias,
+						'inherited_from',
+						CASE WHEN named.depth > 0 THEN named.set_id END
+					)
+				),
+				'[]'
+			)
+			FROM
+				named
+				JOIN emotes ON emotes.id = named.emote_id
+		) AS emotes
+	FROM sets
+	WHERE sets.id = $1
+";
+
 async fn create_set(
 	Conn(conn): Conn,
 	user: AuthUser,
 	Json(body): Json<CreateEmoteSet>,
 ) -> Result<Json<EmoteSet>> {
+	user.require(Scope::SetsWrite)?;
+
+	check_capacity(body.capacity, &user.roles)?;
+
 	let set = conn
 		.query_one(
 			"
@@ -70,17 +192,68 @@ async fn create_set(
 }
 
 async fn update_set(
-	Conn(conn): Conn,
+	Conn(mut conn): Conn,
+	user: AuthUser,
 	Path(id): Path<i64>,
 	Json(body): Json<UpdateEmoteSet>,
 ) -> Result<Json<EmoteSet>> {
-	let set = conn
+	user.require(Scope::SetsWrite)?;
+
+	// Every change is checked against the policy, so an empty update mustn't
+	// get through without any check at all.
+	if body.name.is_none() && body.capacity.is_none() {
+		return Err(JsonError::NothingToUpdate.into());
+	}
+
+	if body.name.is_some() {
+		policy::set(&conn, &user, id, SetAction::Rename).await?;
+	}
+
+	if body.capacity.is_some() {
+		policy::set(&conn, &user, id, SetAction::ChangeCapacity).await?;
+	}
+
+	let transaction = conn.transaction().await?;
+
+	if let Some(capacity) = body.capacity {
+		// The set's owner decides how large it may be, not whichever editor is
+		// making the change.
+		let row = transaction
+			.query_opt(
+				"
+				SELECT
+					users.roles,
+					(
+						SELECT count(*)
+						FROM emotes_to_sets
+						WHERE emotes_to_sets.set_id = sets.id
+					) AS count
+				FROM
+					sets
+					JOIN users ON users.id = sets.user_id
+				WHERE sets.id = $1
+				FOR UPDATE OF sets
+				",
+				&[&id],
+			)
+			.await?
+			.ok_or(JsonError::UnknownEntity("emote set".into()))?;
+
+		let roles: Vec<Role> = row.get("roles");
+		check_capacity(capacity, &roles)?;
+
+		if i64::from(capacity) < row.get::<_, i64>("count") {
+			return Err(JsonError::CapacityBelowUsage.into());
+		}
+	}
+
+	let set = transaction
 		.query_opt(
 			"
 			UPDATE sets
 			SET
-				name = $1,
-				capacity = $2
+				name = COALESCE($1, name),
+				capacity = COALESCE($2, capacity)
 			WHERE id = $3
 			RETURNING *
 			",
@@ -90,41 +263,452 @@ async fn update_set(
 		.ok_or(JsonError::UnknownEntity("emote set".into()))?
 		.into();
 
+	transaction.commit().await?;
+
 	Ok(Json(set))
 }
 
-async fn delete_set(Conn(conn): Conn, user: AuthUser, Path(id): Path<i64>) -> Result<StatusCode> {
+fn check_capacity(capacity: i32, roles: &[Role]) -> Result<()> {
+	let max = policy::max_capacity(roles);
+
+	if (1..=max).contains(&capacity) {
+		Ok(())
+	} else {
+		Err(JsonError::InvalidCapacity(max).into())
+	}
+}
+
+async fn delete_set(
+	State(state): State<AppState>,
+	Conn(conn): Conn,
+	user: AuthUser,
+	Path(id): Path<i64>,
+) -> Result<StatusCode> {
+	user.require(Scope::SetsWrite)?;
+
+	policy::set(&conn, &user, id, SetAction::Delete).await?;
+
+	// The set's emotes are read in the same statement, before the delete
+	// cascades to `emotes_to_sets`.
+	let row = conn
+		.query_one(
+			"
+			WITH emotes AS (
+				SELECT emote_id
+				FROM emotes_to_sets
+				WHERE set_id = $1
+			), deleted AS (
+				DELETE FROM sets
+				WHERE id = $1
+				RETURNING 1
+			)
+			SELECT
+				EXISTS (SELECT 1 FROM deleted) AS deleted,
+				ARRAY(SELECT emote_id FROM emotes) AS emote_ids
+			",
+			&[&id],
+		)
+		.await?;
+
+	if !row.get::<_, bool>("deleted") {
+		return Err(JsonError::UnknownEntity("emote set".into()).into());
+	}
+
+	// Their usage counts are part of their search documents.
+	for emote_id in row.get::<_, Vec<i64>>("emote_ids") {
+		state.search.upsert(emote_id);
+	}
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+async fn add_set_emote(
+	State(state): State<AppState>,
+	Conn(mut conn): Conn,
+	user: AuthUser,
+	Path((set_id, emote_id)): Path<(i64, i64)>,
+) -> Result<StatusCode> {
+	user.require(Scope::SetsWrite)?;
+
+	policy::set(&conn, &user, set_id, SetAction::ManageEmotes).await?;
+
+	let transaction = conn.transaction().await?;
+
+	// Locking the set serialises concurrent adds, so two requests can't both
+	// take the last slot.
+	let capacity: i32 = transaction
+		.query_opt(
+			"SELECT capacity FROM sets WHERE id = $1 FOR UPDATE",
+			&[&set_id],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote set".into()))?
+		.get(0);
+
+	let row = transaction
+		.query_one(
+			"
+			SELECT
+				count(*) AS count,
+				COALESCE(bool_or(emote_id = $2), false) AS present
+			FROM emotes_to_sets
+			WHERE set_id = $1
+			",
+			&[&set_id, &emote_id],
+		)
+		.await?;
+
+	if row.get("present") {
+		return Ok(StatusCode::NO_CONTENT);
+	}
+
+	if row.get::<_, i64>("count") >= i64::from(capacity) {
+		return Err(JsonError::SetFull.into());
+	}
+
+	let name: String = transaction
+		.query_opt("SELECT name FROM emotes WHERE id = $1", &[&emote_id])
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote".into()))?
+		.get(0);
+
+	if name_taken(&transaction, set_id, emote_id, &name).await? {
+		return Err(JsonError::NameConflict(name).into());
+	}
+
+	transaction
+		.execute(
+			"
+			INSERT INTO emotes_to_sets (set_id, emote_id)
+			VALUES ($1, $2)
+			",
+			&[&set_id, &emote_id],
+		)
+		.await
+		.on_constraint(
+			"emotes_to_sets_emote_id_fkey",
+			JsonError::UnknownEntity("emote".into()).into(),
+		)?;
+
+	transaction.commit().await?;
+
+	// The emote's usage count is part of its search document.
+	state.search.upsert(emote_id);
+	state.events.publish(Event::SetEmoteAdded {
+		set_id,
+		emote_id,
+		name,
+	});
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_set_emote(
+	State(state): State<AppState>,
+	Conn(conn): Conn,
+	user: AuthUser,
+	Path((set_id, emote_id)): Path<(i64, i64)>,
+) -> Result<StatusCode> {
+	user.require(Scope::SetsWrite)?;
+
+	policy::set(&conn, &user, set_id, SetAction::ManageEmotes).await?;
+
 	let deleted = conn
 		.query_one(
 			"
 			WITH returned AS (
-				DELETE FROM sets
-				WHERE id = $1 AND user_id = $2
+				DELETE FROM emotes_to_sets
+				WHERE set_id = $1 AND emote_id = $2
 				RETURNING 1
 			)
 			SELECT EXISTS (
 				SELECT 1 FROM returned
 			)
 			",
-			&[&id, &user.id],
+			&[&set_id, &emote_id],
 		)
 		.await?
 		.get(0);
 
 	if deleted {
+		state.search.upsert(emote_id);
+		state
+			.events
+			.publish(Event::SetEmoteRemoved { set_id, emote_id });
+
 		Ok(StatusCode::NO_CONTENT)
 	} else {
-		Err(JsonError::UnknownEntity("emote set".into()).into())
+		Err(JsonError::UnknownEntity("emote".into()).into())
 	}
 }
 
-async fn add_set_emote(
+/// Whether another emote in the set already goes by `name`, either as its own
+/// name or as an alias. Callers hold the set's row lock so the answer stays
+/// true until they commit.
+async fn name_taken(
+	transaction: &Transaction<'_>,
+	set_id: i64,
+	emote_id: i64,
+	name: &str,
+) -> Result<bool> {
+	let taken = transaction
+		.query_one(
+			"
+			SELECT EXISTS (
+				SELECT 1
+				FROM
+					emotes_to_sets AS m2m
+					JOIN emotes ON emotes.id = m2m.emote_id
+				WHERE
+					m2m.set_id = $1
+					AND m2m.emote_id != $2
+					AND COALESCE(m2m.alias, emotes.name) = $3
+			)
+			",
+			&[&set_id, &emote_id, &name],
+		)
+		.await?
+		.get(0);
+
+	Ok(taken)
+}
+
+async fn set_emote_alias(
+	State(state): State<AppState>,
+	Conn(mut conn): Conn,
+	user: AuthUser,
+	Path((set_id, emote_id)): Path<(i64, i64)>,
+	Json(body): Json<SetEmoteAlias>,
+) -> Result<StatusCode> {
+	user.require(Scope::SetsWrite)?;
+
+	policy::set(&conn, &user, set_id, SetAction::ManageEmotes).await?;
+
+	let alias = body.alias.trim();
+
+	if alias.is_empty()
+		|| alias.chars().count() > MAX_ALIAS_LENGTH
+		|| alias.contains(char::is_whitespace)
+	{
+		return Err(JsonError::InvalidField("alias".into()).into());
+	}
+
+	let transaction = conn.transaction().await?;
+
+	transaction
+		.execute("SELECT 1 FROM sets WHERE id = $1 FOR UPDATE", &[&set_id])
+		.await?;
+
+	if name_taken(&transaction, set_id, emote_id, alias).await? {
+		return Err(JsonError::NameConflict(alias.into()).into());
+	}
+
+	let updated = transaction
+		.execute(
+			"UPDATE emotes_to_sets SET alias = $3 WHERE set_id = $1 AND emote_id = $2",
+			&[&set_id, &emote_id, &alias],
+		)
+		.await?;
+
+	if updated == 0 {
+		return Err(JsonError::UnknownEntity("emote".into()).into());
+	}
+
+	transaction.commit().await?;
+
+	state.events.publish(Event::SetEmoteRenamed {
+		set_id,
+		emote_id,
+		name: alias.into(),
+	});
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+/// Goes back to the emote's own name, which has to be free in the set too.
+async fn clear_emote_alias(
+	State(state): State<AppState>,
+	Conn(mut conn): Conn,
+	user: AuthUser,
+	Path((set_id, emote_id)): Path<(i64, i64)>,
+) -> Result<StatusCode> {
+	user.require(Scope::SetsWrite)?;
+
+	policy::set(&conn, &user, set_id, SetAction::ManageEmotes).await?;
+
+	let transaction = conn.transaction().await?;
+
+	transaction
+		.execute("SELECT 1 FROM sets WHERE id = $1 FOR UPDATE", &[&set_id])
+		.await?;
+
+	let name: String = transaction
+		.query_opt(
+			"
+			SELECT emotes.name
+			FROM
+				emotes_to_sets AS m2m
+				JOIN emotes ON emotes.id = m2m.emote_id
+			WHERE m2m.set_id = $1 AND m2m.emote_id = $2
+			",
+			&[&set_id, &emote_id],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote".into()))?
+		.get(0);
+
+	if name_taken(&transaction, set_id, emote_id, &name).await? {
+		return Err(JsonError::NameConflict(name).into());
+	}
+
+	transaction
+		.execute(
+			"UPDATE emotes_to_sets SET alias = NULL WHERE set_id = $1 AND emote_id = $2",
+			&[&set_id, &emote_id],
+		)
+		.await?;
+
+	transaction.commit().await?;
+
+	state.events.publish(Event::SetEmoteRenamed {
+		set_id,
+		emote_id,
+		name,
+	});
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+/// Makes a set inherit from another. The new parent can't be the set itself or
+/// one of its descendants, and the whole chain, including any sets already
+/// inheriting from this one, has to stay within [`MAX_INHERITANCE_DEPTH`].
+async fn set_set_parent(
+	Conn(mut conn): Conn,
+	user: AuthUser,
+	Path((id, parent_id)): Path<(i64, i64)>,
+) -> Result<Json<EmoteSet>> {
+	user.require(Scope::SetsWrite)?;
+
+	policy::set(&conn, &user, id, SetAction::ChangeParent).await?;
+
+	if id == parent_id {
+		return Err(JsonError::SetCycle.into());
+	}
+
+	let transaction = conn.transaction().await?;
+
+	transaction
+		.execute("SELECT pg_advisory_xact_lock($1)", &[&PARENT_LOCK])
+		.await?;
+
+	// How many sets sit at and above the new parent, and whether this set is
+	// one of them.
+	let above = transaction
+		.query_opt(
+			"
+			WITH RECURSIVE chain AS (
+				SELECT id, parent_id, 1 AS depth
+				FROM sets
+				WHERE id = $1
+
+				UNION ALL
+
+				SELECT sets.id, sets.parent_id, chain.depth + 1
+				FROM
+					sets
+					JOIN chain ON sets.id = chain.parent_id
+				WHERE sets.id != $2 AND chain.depth <= $3
+			)
+			SELECT
+				max(depth) AS depth,
+				bool_or(parent_id = $2) AS cycle
+			FROM chain
+			HAVING count(*) > 0
+			",
+			&[&parent_id, &id, &MAX_INHERITANCE_DEPTH],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote set".into()))?;
+
+	if above.get::<_, Option<bool>>("cycle").unwrap_or(false) {
+		return Err(JsonError::SetCycle.into());
+	}
+
+	// How many levels of sets already inherit from this one.
+	let below: i32 = transaction
+		.query_one(
+			"
+			WITH RECURSIVE tree AS (
+				SELECT id, 0 AS depth
+				FROM sets
+				WHERE id = $1
+
+				UNION ALL
+
+				SELECT sets.id, tree.depth + 1
+				FROM
+					sets
+					JOIN tree ON sets.parent_id = tree.id
+				WHERE tree.depth <= $2
+			)
+			SELECT max(depth) FROM tree
+			",
+			&[&id, &MAX_INHERITANCE_DEPTH],
+		)
+		.await?
+		.get(0);
+
+	if above.get::<_, i32>("depth") + below > MAX_INHERITANCE_DEPTH {
+		return Err(JsonError::InheritanceTooDeep(MAX_INHERITANCE_DEPTH).into());
+	}
+
+	let set = transaction
+		.query_one(
+			"UPDATE sets SET parent_id = $2 WHERE id = $1 RETURNING *",
+			&[&id, &parent_id],
+		)
+		.await?
+		.into();
+
+	transaction.commit().await?;
+
+	Ok(Json(set))
+}
+
+async fn clear_set_parent(
 	Conn(conn): Conn,
+	user: AuthUser,
+	Path(id): Path<i64>,
+) -> Result<Json<EmoteSet>> {
+	user.require(Scope::SetsWrite)?;
+
+	policy::set(&conn, &user, id, SetAction::ChangeParent).await?;
+
+	let set = conn
+		.query_opt(
+			"UPDATE sets SET parent_id = NULL WHERE id = $1 RETURNING *",
+			&[&id],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote set".into()))?
+		.into();
+
+	Ok(Json(set))
+}
+
+/// Hides an emote the set would otherwise inherit.
+async fn add_set_exclusion(
+	Conn(conn): Conn,
+	user: AuthUser,
 	Path((set_id, emote_id)): Path<(i64, i64)>,
 ) -> Result<StatusCode> {
+	user.require(Scope::SetsWrite)?;
+
+	policy::set(&conn, &user, set_id, SetAction::ManageEmotes).await?;
+
 	conn.execute(
 		"
-		INSERT INTO emotes_to_sets (set_id, emote_id)
+		INSERT INTO set_exclusions (set_id, emote_id)
 		VALUES ($1, $2)
 		ON CONFLICT DO NOTHING
 		",
@@ -132,49 +716,147 @@ async fn add_set_emote(
 	)
 	.await
 	.on_constraint(
-		"emotes_to_sets_set_id_fkey",
-		JsonError::UnknownEntity("emote set".into()).into(),
-	)
-	.on_constraint(
-		"emotes_to_sets_emote_id_fkey",
+		"set_exclusions_emote_id_fkey",
 		JsonError::UnknownEntity("emote".into()).into(),
 	)?;
 
 	Ok(StatusCode::NO_CONTENT)
 }
 
-async fn remove_set_emote(
+async fn remove_set_exclusion(
 	Conn(conn): Conn,
+	user: AuthUser,
 	Path((set_id, emote_id)): Path<(i64, i64)>,
 ) -> Result<StatusCode> {
-	let exists = conn
-		.query_opt("SELECT id FROM sets WHERE id = $1", &[&set_id])
+	user.require(Scope::SetsWrite)?;
+
+	policy::set(&conn, &user, set_id, SetAction::ManageEmotes).await?;
+
+	let deleted = conn
+		.execute(
+			"DELETE FROM set_exclusions WHERE set_id = $1 AND emote_id = $2",
+			&[&set_id, &emote_id],
+		)
 		.await?;
 
-	if exists.is_none() {
-		return Err(JsonError::UnknownEntity("emote set".into()).into());
+	if deleted > 0 {
+		Ok(StatusCode::NO_CONTENT)
+	} else {
+		Err(JsonError::UnknownEntity("emote".into()).into())
 	}
+}
 
-	let deleted = conn
+/// Copies a set, its emotes, aliases, exclusions and parent into a new set
+/// owned by the caller. The copy has to fit within the caller's own capacity
+/// limit.
+async fn fork_set(
+	State(state): State<AppState>,
+	Conn(mut conn): Conn,
+	user: AuthUser,
+	Path(id): Path<i64>,
+	body: Option<Json<ForkEmoteSet>>,
+) -> Result<(StatusCode, Json<EmoteSet>)> {
+	user.require(Scope::SetsWrite)?;
+
+	let body = body.map(|Json(body)| body).unwrap_or_default();
+	let transaction = conn.transaction().await?;
+
+	// The source is locked against changes so the copy is consistent.
+	let source = transaction
+		.query_opt(
+			"
+			SELECT
+				name,
+				capacity,
+				parent_id,
+				(
+					SELECT count(*)
+					FROM emotes_to_sets
+					WHERE emotes_to_sets.set_id = sets.id
+				) AS count
+			FROM sets
+			WHERE id = $1
+			FOR SHARE
+			",
+			&[&id],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("emote set".into()))?;
+
+	let max = policy::max_capacity(&user.roles);
+	let count: i64 = source.get("count");
+
+	if count > i64::from(max) {
+		return Err(JsonError::ForkTooLarge(max).into());
+	}
+
+	let name = match body.name {
+		Some(name) if !name.trim().is_empty() => name.trim().to_owned(),
+		Some(_) => return Err(JsonError::InvalidField("name".into()).into()),
+		None => source.get("name"),
+	};
+	let capacity = source.get::<_, i32>("capacity").min(max);
+	let parent_id: Option<i64> = source.get("parent_id");
+	let fork_id = Snowflake::new().0;
+
+	let set = transaction
 		.query_one(
 			"
-			WITH returned AS (
-				DELETE FROM emotes_to_sets
-				WHERE set_id = $1 AND emote_id = $2
-				RETURNING 1
-			)
-			SELECT EXISTS (
-				SELECT 1 FROM returned
-			)
+			INSERT INTO sets (id, name, capacity, user_id, parent_id, origin_id)
+			VALUES ($1, $2, $3, $4, $5, $6)
+			RETURNING *
 			",
-			&[&set_id, &emote_id],
+			&[&fork_id, &name, &capacity, &user.id, &parent_id, &id],
+		)
+		.await?;
+
+	let emote_ids: Vec<i64> = transaction
+		.query(
+			"
+			INSERT INTO emotes_to_sets (set_id, emote_id, alias)
+			SELECT $2, emote_id, alias
+			FROM emotes_to_sets
+			WHERE set_id = $1
+			RETURNING emote_id
+			",
+			&[&id, &fork_id],
 		)
 		.await?
-		.get(0);
+		.iter()
+		.map(|row| row.get(0))
+		.collect();
 
-	if deleted {
-		Ok(StatusCode::NO_CONTENT)
-	} else {
-		Err(JsonError::UnknownEntity("emote".into()).into())
+	transaction
+		.execute(
+			"
+			INSERT INTO set_exclusions (set_id, emote_id)
+			SELECT $2, emote_id
+			FROM set_exclusions
+			WHERE set_id = $1
+			",
+			&[&id, &fork_id],
+		)
+		.await?;
+
+	transaction.commit().await?;
+
+	for emote_id in emote_ids {
+		state.search.upsert(emote_id);
 	}
+
+	Ok((StatusCode::CREATED, Json(set.into())))
+}
+
+async fn get_set_forks(Conn(conn): Conn, Path(id): Path<i64>) -> Result<Json<Vec<EmoteSet>>> {
+	let forks = conn
+		.query(
+			"SELECT * FROM sets WHERE origin_id = $1 ORDER BY id",
+			&[&id],
+		)
+		.await?
+		.into_iter()
+		.map(|row| row.into())
+		.collect();
+
+	Ok(Json(forks))
 }
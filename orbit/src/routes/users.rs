@@ -1,13 +1,16 @@
-use axum::extract::{Json, Path};
+use axum::extract::{Json, Path, State};
 use axum::http::StatusCode;
 use axum::routing::{delete, get, put};
 use axum::Router;
 use orbit_types::models::emote::Emote;
+use orbit_types::models::event::Event;
+use orbit_types::models::token::Scope;
 use orbit_types::models::user::*;
 
 use crate::auth::{self, AuthUser};
 use crate::db::{Conn, Connection};
 use crate::error::{Error, JsonError, ResultExt};
+use crate::policy::{self, SetAction};
 use crate::{AppState, Result};
 
 pub fn router(state: &AppState) -> Router<AppState> {
@@ -15,6 +18,7 @@ pub fn router(state: &AppState) -> Router<AppState> {
 		.route("/users/@me", get(get_current_user))
 		.route("/users/@me/editors/:id", put(add_user_editor))
 		.route("/users/@me/editors/:id", delete(remove_user_editor))
+		.route("/users/:id/sets/@channel/:setId", put(set_user_channel_set))
 		.route_layer(axum::middleware::from_fn_with_state(
 			state.clone(),
 			auth::middleware,
@@ -27,21 +31,32 @@ pub fn router(state: &AppState) -> Router<AppState> {
 }
 
 async fn get_current_user(user: AuthUser) -> Result<Json<User>> {
-	Ok(Json(user))
+	user.require(Scope::UsersRead)?;
+
+	Ok(Json(user.user))
 }
 
 async fn add_user_editor(
 	Conn(conn): Conn,
 	user: AuthUser,
 	Path(id): Path<i64>,
+	body: Option<Json<UpdateEditor>>,
 ) -> Result<StatusCode> {
+	user.require(Scope::UsersWrite)?;
+
+	let body = body.map(|Json(body)| body).unwrap_or_default();
+
 	conn.execute(
 		"
-		INSERT INTO users_to_editors (user_id, editor_id)
-		VALUES ($1, $2)
-		ON CONFLICT DO NOTHING
+		INSERT INTO users_to_editors (user_id, editor_id, permissions)
+		VALUES (
+			$1, $2,
+			COALESCE($3, '{manage_emotes,rename_sets,change_capacity}')
+		)
+		ON CONFLICT (user_id, editor_id) DO UPDATE
+		SET permissions = COALESCE($3, users_to_editors.permissions)
 		",
-		&[&user.id, &id],
+		&[&user.id, &id, &body.permissions],
 	)
 	.await
 	.on_constraint("user_cannot_add_self", JsonError::UserCannotAddSelf.into())?;
@@ -54,6 +69,8 @@ async fn remove_user_editor(
 	user: AuthUser,
 	Path(id): Path<i64>,
 ) -> Result<StatusCode> {
+	user.require(Scope::UsersWrite)?;
+
 	let deleted = conn
 		.query_one(
 			"
@@ -88,12 +105,13 @@ async fn get_user(Conn(conn): Conn, Path(id): Path<i64>) -> Result<Json<User>> {
 	Ok(Json(user))
 }
 
-async fn get_user_editors(Conn(conn): Conn, Path(id): Path<i64>) -> Result<Json<Vec<User>>> {
+async fn get_user_editors(Conn(conn): Conn, Path(id): Path<i64>) -> Result<Json<Vec<Editor>>> {
 	let editors = conn
 		.query(
 			"
 			SELECT
-				editor.*
+				to_jsonb(editor.*) AS user,
+				m2m.permissions
 			FROM
 				users
 				JOIN users_to_editors AS m2m ON users.id = m2m.user_id
@@ -118,12 +136,18 @@ async fn get_user_emotes(Conn(conn): Conn, Path(id): Path<i64>) -> Result<Json<V
 	let emotes = conn
 		.query(
 			"
-			SELECT emotes.*
+			SELECT
+				emotes.*,
+				(
+					SELECT COALESCE(array_agg(versions.id ORDER BY versions.id), '{}')
+					FROM versions
+					WHERE versions.emote_id = emotes.id
+				) AS versions
 			FROM
 				users
 				JOIN emotes ON users.id = emotes.user_id
 			WHERE user_id = $1
-			ORDER BY id
+			ORDER BY emotes.id
 			",
 			&[&id],
 		)
@@ -178,6 +202,49 @@ async fn get_user_channel_set(Conn(conn): Conn, Path(id): Path<i64>) -> Result<J
 	Ok(Json(set))
 }
 
+/// Switches which of a user's sets is used in their channel. Their editors
+/// may do this too, as long as they can manage emotes.
+async fn set_user_channel_set(
+	State(state): State<AppState>,
+	Conn(conn): Conn,
+	user: AuthUser,
+	Path((id, set_id)): Path<(i64, i64)>,
+) -> Result<Json<UserEmoteSet>> {
+	user.require(Scope::UsersWrite)?;
+	policy::set(&conn, &user, set_id, SetAction::Activate).await?;
+
+	let set = conn
+		.query_opt(
+			"
+			WITH updated AS (
+				UPDATE users
+				SET channel_set_id = $2
+				WHERE
+					id = $1
+					AND EXISTS (
+						SELECT 1 FROM sets WHERE id = $2 AND user_id = $1
+					)
+				RETURNING channel_set_id
+			)
+			SELECT sets.*
+			FROM
+				updated
+				JOIN sets ON updated.channel_set_id = sets.id
+			",
+			&[&id, &set_id],
+		)
+		.await?
+		.ok_or(JsonError::SetNotOwned)?
+		.into();
+
+	state.events.publish(Event::ChannelSetUpdated {
+		user_id: id,
+		set_id,
+	});
+
+	Ok(Json(set))
+}
+
 async fn user_exists(conn: &Connection, id: &i64) -> bool {
 	conn.query_one("SELECT EXISTS (SELECT id FROM users WHERE id = $1)", &[&id])
 		.await
@@ -0,0 +1,201 @@
+use axum::extract::{Json, Path};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, patch, post};
+use axum::Router;
+use orbit_types::models::webhook::*;
+use orbit_types::Snowflake;
+use reqwest::Url;
+
+use crate::auth::{self, AuthSession};
+use crate::db::Conn;
+use crate::error::JsonError;
+use crate::{webhooks, AppState, Result};
+
+const MAX_WEBHOOKS: i64 = 10;
+
+/// How many of a webhook's most recent deliveries are listed.
+const DELIVERY_LIMIT: i64 = 100;
+
+/// Webhooks are managed like API tokens, from a session only, since their
+/// secrets are handed out on creation.
+pub fn router(state: &AppState) -> Router<AppState> {
+	Router::new()
+		.route("/webhooks", get(get_webhooks))
+		.route("/webhooks", post(create_webhook))
+		.route("/webhooks/:id", patch(update_webhook))
+		.route("/webhooks/:id", delete(delete_webhook))
+		.route("/webhooks/:id/deliveries", get(get_webhook_deliveries))
+		.route_layer(axum::middleware::from_fn_with_state(
+			state.clone(),
+			auth::middleware,
+		))
+}
+
+/// Webhooks have to use HTTPS since their payloads are only signed, not
+/// encrypted, and can't point at anything on our own network.
+async fn validate_url(url: &str) -> Result<()> {
+	match Url::parse(url) {
+		Ok(url) if url.scheme() == "https" && webhooks::is_public_url(&url).await => Ok(()),
+		_ => Err(JsonError::InvalidField("url".into()).into()),
+	}
+}
+
+async fn get_webhooks(Conn(conn): Conn, session: AuthSession) -> Result<Json<Vec<Webhook>>> {
+	let webhooks = conn
+		.query(
+			"SELECT * FROM webhooks WHERE user_id = $1 ORDER BY id",
+			&[&session.user_id],
+		)
+		.await?
+		.into_iter()
+		.map(|row| row.into())
+		.collect();
+
+	Ok(Json(webhooks))
+}
+
+async fn create_webhook(
+	Conn(mut conn): Conn,
+	session: AuthSession,
+	Json(body): Json<CreateWebhook>,
+) -> Result<(StatusCode, Json<NewWebhook>)> {
+	validate_url(&body.url).await?;
+
+	if body.events.is_empty() {
+		return Err(JsonError::MissingField("events".into()).into());
+	}
+
+	let secret = auth::random_token();
+	let transaction = conn.transaction().await?;
+
+	// Locking the user makes concurrent requests count one at a time, so they
+	// can't all get in under the limit.
+	transaction
+		.execute(
+			"SELECT 1 FROM users WHERE id = $1 FOR UPDATE",
+			&[&session.user_id],
+		)
+		.await?;
+
+	let webhook = transaction
+		.query_opt(
+			"
+			INSERT INTO webhooks (id, user_id, url, secret, events)
+			SELECT $1, $2, $3, $4, $5
+			WHERE (SELECT count(*) FROM webhooks WHERE user_id = $2) < $6
+			RETURNING *
+			",
+			&[
+				&Snowflake::new().0,
+				&session.user_id,
+				&body.url,
+				&secret,
+				&body.events,
+				&MAX_WEBHOOKS,
+			],
+		)
+		.await?
+		.ok_or(JsonError::TooManyWebhooks(MAX_WEBHOOKS))?
+		.into();
+
+	transaction.commit().await?;
+
+	Ok((StatusCode::CREATED, Json(NewWebhook { webhook, secret })))
+}
+
+async fn update_webhook(
+	Conn(conn): Conn,
+	session: AuthSession,
+	Path(id): Path<i64>,
+	Json(body): Json<UpdateWebhook>,
+) -> Result<Json<Webhook>> {
+	if let Some(url) = &body.url {
+		validate_url(url).await?;
+	}
+
+	if body.events.as_ref().is_some_and(Vec::is_empty) {
+		return Err(JsonError::MissingField("events".into()).into());
+	}
+
+	let webhook = conn
+		.query_opt(
+			"
+			UPDATE webhooks
+			SET
+				url = COALESCE($3, url),
+				events = COALESCE($4, events),
+				enabled = COALESCE($5, enabled),
+				failures = CASE WHEN $5 THEN 0 ELSE failures END
+			WHERE id = $1 AND user_id = $2
+			RETURNING *
+			",
+			&[
+				&id,
+				&session.user_id,
+				&body.url,
+				&body.events,
+				&body.enabled,
+			],
+		)
+		.await?
+		.ok_or(JsonError::UnknownEntity("webhook".into()))?
+		.into();
+
+	Ok(Json(webhook))
+}
+
+async fn delete_webhook(
+	Conn(conn): Conn,
+	session: AuthSession,
+	Path(id): Path<i64>,
+) -> Result<StatusCode> {
+	let deleted = conn
+		.execute(
+			"DELETE FROM webhooks WHERE id = $1 AND user_id = $2",
+			&[&id, &session.user_id],
+		)
+		.await?;
+
+	if deleted > 0 {
+		Ok(StatusCode::NO_CONTENT)
+	} else {
+		Err(JsonError::UnknownEntity("webhook".into()).into())
+	}
+}
+
+/// The most recent deliveries first.
+async fn get_webhook_deliveries(
+	Conn(conn): Conn,
+	session: AuthSession,
+	Path(id): Path<i64>,
+) -> Result<Json<Vec<WebhookDelivery>>> {
+	let exists: bool = conn
+		.query_one(
+			"SELECT EXISTS (SELECT 1 FROM webhooks WHERE id = $1 AND user_id = $2)",
+			&[&id, &session.user_id],
+		)
+		.await?
+		.get(0);
+
+	if !exists {
+		return Err(JsonError::UnknownEntity("webhook".into()).into());
+	}
+
+	let deliveries = conn
+		.query(
+			"
+			SELECT *
+			FROM webhook_deliveries
+			WHERE webhook_id = $1
+			ORDER BY id DESC
+			LIMIT $2
+			",
+			&[&id, &DELIVERY_LIMIT],
+		)
+		.await?
+		.into_iter()
+		.map(|row| row.into())
+		.collect();
+
+	Ok(Json(deliveries))
+}
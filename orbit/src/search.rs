@@ -0,0 +1,326 @@
+//! Keeps the Meilisearch `emotes` index in step with the database.
+//!
+//! Handlers report which emotes changed through [`SearchSync`] and a single
+//! background worker batches those changes, loads the current rows and pushes
+//! them to the index. Because the worker handles one batch at a time, a full
+//! reindex never races with incremental updates.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use meilisearch_sdk::client::Client as MeilisearchClient;
+use meilisearch_sdk::indexes::Index;
+use meilisearch_sdk::settings::Settings;
+use meilisearch_sdk::task_info::TaskInfo;
+use meilisearch_sdk::tasks::Task;
+use meilisearch_sdk::SwapIndexes;
+use orbit_types::Snowflake;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_postgres::Row;
+
+use crate::db::Pool;
+use crate::error::Error;
+use crate::Result;
+
+pub const EMOTES_INDEX: &str = "emotes";
+
+/// The full reindex is built here and then swapped in, so searches keep
+/// working while it runs.
+const REINDEX_INDEX: &str = "emotes_reindex";
+
+const BATCH_SIZE: usize = 500;
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+const REINDEX_PAGE_SIZE: i64 = 1000;
+
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// An emote as stored in the search index. Only what is needed to match, filter
+/// and sort is indexed; hits are loaded from the database afterwards.
+///
+/// Ids are stored as strings since Meilisearch can't represent every `i64`
+/// exactly as a number.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EmoteDocument {
+	pub id: String,
+	pub name: String,
+	pub tags: Vec<String>,
+	pub animated: bool,
+	pub modifier: bool,
+	pub nsfw: bool,
+	pub approved: bool,
+	pub public: bool,
+	pub user_id: String,
+
+	/// Milliseconds since the Unix epoch, taken from the emote's id.
+	pub created_at: i64,
+
+	/// How many sets the emote is in.
+	pub usage: i64,
+}
+
+impl From<Row> for EmoteDocument {
+	fn from(row: Row) -> Self {
+		let id: i64 = row.get("id");
+		let user_id: i64 = row.get("user_id");
+
+		Self {
+			id: id.to_string(),
+			name: row.get("name"),
+			tags: row.get("tags"),
+			animated: row.get("animated"),
+			modifier: row.get("modifier"),
+			nsfw: row.get("nsfw"),
+			approved: row.get("approved"),
+			public: row.get("public"),
+			user_id: user_id.to_string(),
+			created_at: Snowflake(id).timestamp(),
+			usage: row.get("usage"),
+		}
+	}
+}
+
+const DOCUMENT_COLUMNS: &str = "
+	emotes.id, emotes.name, emotes.tags, emotes.animated, emotes.modifier,
+	emotes.nsfw, emotes.approved, emotes.public, emotes.user_id,
+	(
+		SELECT count(*)
+		FROM emotes_to_sets
+		WHERE emotes_to_sets.emote_id = emotes.id
+	) AS usage
+";
+
+pub fn emotes(ms: &MeilisearchClient) -> Index {
+	ms.index(EMOTES_INDEX)
+}
+
+pub async fn configure(index: &Index) -> Result<(), meilisearch_sdk::Error> {
+	let settings = Settings::new()
+		.with_searchable_attributes(["name", "tags"])
+		.with_filterable_attributes([
+			"animated", "modifier", "nsfw", "approved", "public", "user_id",
+		])
+		.with_sortable_attributes(["created_at", "usage", "name"]);
+
+	index.set_settings(&settings).await?;
+
+	Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Change {
+	Upsert(i64),
+	Delete(i64),
+	Reindex,
+}
+
+/// A handle to the indexing worker. Changes are queued and never fail the
+/// request that caused them.
+#[derive(Clone)]
+pub struct SearchSync {
+	sender: mpsc::UnboundedSender<Change>,
+}
+
+impl SearchSync {
+	/// Starts the worker. A full reindex is queued straight away so anything
+	/// missed while the service was down is picked up.
+	pub fn spawn(ms: MeilisearchClient, pool: Pool) -> Self {
+		let (sender, receiver) = mpsc::unbounded_channel();
+		let sync = Self { sender };
+
+		sync.reindex();
+		tokio::spawn(run(ms, pool, receiver));
+
+		sync
+	}
+
+	pub fn upsert(&self, id: i64) {
+		self.send(Change::Upsert(id));
+	}
+
+	pub fn delete(&self, id: i64) {
+		self.send(Change::Delete(id));
+	}
+
+	pub fn reindex(&self) {
+		self.send(Change::Reindex);
+	}
+
+	fn send(&self, change: Change) {
+		if self.sender.send(change).is_err() {
+			tracing::error!(?change, "Search sync worker has stopped");
+		}
+	}
+}
+
+async fn run(ms: MeilisearchClient, pool: Pool, mut receiver: mpsc::UnboundedReceiver<Change>) {
+	while let Some(first) = receiver.recv().await {
+		// Only the latest change to each emote matters.
+		let mut changes = HashMap::new();
+		let mut reindex = false;
+		let deadline = Instant::now() + BATCH_INTERVAL;
+		let mut next = Some(first);
+
+		while let Some(change) = next {
+			match change {
+				Change::Upsert(id) | Change::Delete(id) => {
+					changes.insert(id, change);
+				}
+				Change::Reindex => reindex = true,
+			}
+
+			if changes.len() >= BATCH_SIZE {
+				break;
+			}
+
+			next = tokio::time::timeout_at(deadline, receiver.recv())
+				.await
+				.ok()
+				.flatten();
+		}
+
+		if reindex {
+			retry("reindex", || full_reindex(&ms, &pool)).await;
+		}
+
+		if !changes.is_empty() {
+			let changes = changes.into_values().collect::<Vec<_>>();
+
+			retry("sync", || apply(&ms, &pool, &changes)).await;
+		}
+	}
+}
+
+async fn retry<F, Fut>(operation: &str, mut f: F)
+where
+	F: FnMut() -> Fut,
+	Fut: std::future::Future<Output = Result<()>>,
+{
+	for attempt in 1..=MAX_ATTEMPTS {
+		match f().await {
+			Ok(()) => return,
+			Err(err) if attempt < MAX_ATTEMPTS => {
+				tracing::warn!(?err, attempt, "Search {operation} failed, retrying");
+				tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+			}
+			Err(err) => tracing::error!(?err, "Search {operation} failed, giving up"),
+		}
+	}
+}
+
+async fn apply(ms: &MeilisearchClient, pool: &Pool, changes: &[Change]) -> Result<()> {
+	let mut upserts = vec![];
+	let mut deletes = vec![];
+
+	for change in changes {
+		match *change {
+			Change::Upsert(id) => upserts.push(id),
+			Change::Delete(id) => deletes.push(id.to_string()),
+			Change::Reindex => (),
+		}
+	}
+
+	let index = emotes(ms);
+
+	if !upserts.is_empty() {
+		let conn = pool.get().await.map_err(|_| Error::Generic)?;
+		let documents = conn
+			.query(
+				&format!("SELECT {DOCUMENT_COLUMNS} FROM emotes WHERE emotes.id = ANY($1)"),
+				&[&upserts],
+			)
+			.await?
+			.into_iter()
+			.map(EmoteDocument::from)
+			.collect::<Vec<_>>();
+
+		// Emotes deleted before the batch was applied are removed instead.
+		for id in &upserts {
+			let id = id.to_string();
+
+			if !documents.iter().any(|document| document.id == id) {
+				deletes.push(id);
+			}
+		}
+
+		if !documents.is_empty() {
+			wait(ms, index.add_or_replace(&documents, Some("id")).await?).await?;
+		}
+	}
+
+	if !deletes.is_empty() {
+		wait(ms, index.delete_documents(&deletes).await?).await?;
+	}
+
+	Ok(())
+}
+
+/// Rebuilds the index from scratch, walking `emotes` in id (and so creation)
+/// order.
+async fn full_reindex(ms: &MeilisearchClient, pool: &Pool) -> Result<()> {
+	// Whatever is left over from a reindex that didn't finish is thrown away,
+	// so a failure here only means there was nothing to delete.
+	ms.delete_index(REINDEX_INDEX)
+		.await?
+		.wait_for_completion(ms, None, None)
+		.await?;
+
+	wait(ms, ms.create_index(REINDEX_INDEX, Some("id")).await?).await?;
+
+	let index = ms.index(REINDEX_INDEX);
+	configure(&index).await?;
+
+	let conn = pool.get().await.map_err(|_| Error::Generic)?;
+	let query = format!(
+		"
+		SELECT {DOCUMENT_COLUMNS}
+		FROM emotes
+		WHERE emotes.id > $1
+		ORDER BY emotes.id
+		LIMIT $2
+		"
+	);
+
+	let mut last_id = i64::MIN;
+	let mut count = 0;
+
+	loop {
+		let rows = conn.query(&query, &[&last_id, &REINDEX_PAGE_SIZE]).await?;
+
+		let Some(last) = rows.last() else {
+			break;
+		};
+
+		last_id = last.get("id");
+		count += rows.len();
+
+		let documents = rows
+			.into_iter()
+			.map(EmoteDocument::from)
+			.collect::<Vec<_>>();
+
+		wait(ms, index.add_or_replace(&documents, Some("id")).await?).await?;
+	}
+
+	let swap = SwapIndexes {
+		indexes: (EMOTES_INDEX.to_owned(), REINDEX_INDEX.to_owned()),
+	};
+
+	wait(ms, ms.swap_indexes([&swap]).await?).await?;
+	wait(ms, ms.delete_index(REINDEX_INDEX).await?).await?;
+
+	tracing::info!(count, "Reindexed emotes");
+
+	Ok(())
+}
+
+/// Meilisearch only queues writes, so a batch has to wait for its task to know
+/// whether it actually needs retrying.
+async fn wait(ms: &MeilisearchClient, task: TaskInfo) -> Result<()> {
+	match task.wait_for_completion(ms, None, None).await? {
+		Task::Failed { content } => Err(meilisearch_sdk::Error::Meilisearch(content.error).into()),
+		_ => Ok(()),
+	}
+}
@@ -0,0 +1,234 @@
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use super::{storage_error, Storage};
+use crate::error::Error;
+use crate::Result;
+
+/// Stores files on disk, for development and tests that shouldn't need AWS.
+/// The directory is served by the API itself under `/cdn`.
+pub struct LocalStorage {
+	root: PathBuf,
+	prefix: String,
+	url: String,
+}
+
+impl LocalStorage {
+	pub fn new(root: PathBuf, prefix: String, url: String) -> Self {
+		Self { root, prefix, url }
+	}
+
+	fn path(&self, key: &str) -> Result<PathBuf> {
+		let key = format!("{}{key}", self.prefix);
+		let relative = Path::new(&key);
+
+		// Keys never come from clients, but refuse anything that would escape
+		// the storage directory regardless.
+		if !relative
+			.components()
+			.all(|component| matches!(component, Component::Normal(_)))
+		{
+			return Err(Error::Cdn);
+		}
+
+		Ok(self.root.join(relative))
+	}
+}
+
+#[axum::async_trait]
+impl Storage for LocalStorage {
+	async fn put(&self, key: &str, body: Vec<u8>, _content_type: &str) -> Result<()> {
+		let path = self.path(key)?;
+
+		if let Some(parent) = path.parent() {
+			tokio::fs::create_dir_all(parent)
+				.await
+				.map_err(storage_error)?;
+		}
+
+		tokio::fs::write(path, body).await.map_err(storage_error)
+	}
+
+	async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+		match tokio::fs::read(self.path(key)?).await {
+			Ok(body) => Ok(Some(body)),
+			Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+			Err(err) => Err(storage_error(err)),
+		}
+	}
+
+	async fn delete(&self, keys: &[String]) -> Result<()> {
+		for key in keys {
+			match tokio::fs::remove_file(self.path(key)?).await {
+				Ok(()) => (),
+				Err(err) if err.kind() == ErrorKind::NotFound => (),
+				Err(err) => return Err(storage_error(err)),
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn list_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+		let full_prefix = format!("{}{prefix}", self.prefix);
+
+		// Walk the deepest directory the prefix names, then filter by the
+		// remainder, which may be a partial file name.
+		let dir = match full_prefix.rfind('/') {
+			Some(i) => self.root.join(&full_prefix[..i]),
+			None => self.root.clone(),
+		};
+
+		let mut keys = vec![];
+		let mut pending = vec![dir];
+
+		while let Some(dir) = pending.pop() {
+			let mut entries = match tokio::fs::read_dir(&dir).await {
+				Ok(entries) => entries,
+				Err(err) if err.kind() == ErrorKind::NotFound => continue,
+				Err(err) => return Err(storage_error(err)),
+			};
+
+			while let Some(entry) = entries.next_entry().await.map_err(storage_error)? {
+				let path = entry.path();
+
+				if entry.file_type().await.map_err(storage_error)?.is_dir() {
+					pending.push(path);
+					continue;
+				}
+
+				let Ok(relative) = path.strip_prefix(&self.root) else {
+					continue;
+				};
+
+				let key = relative
+					.components()
+					.map(|component| component.as_os_str().to_string_lossy())
+					.collect::<Vec<_>>()
+					.join("/");
+
+				if let Some(key) = key.strip_prefix(&full_prefix) {
+					keys.push(format!("{prefix}{key}"));
+				}
+			}
+		}
+
+		Ok(keys)
+	}
+
+	fn url(&self, key: &str) -> String {
+		format!("{}/{}{key}", self.url, self.prefix)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::storage::{emote_prefix, version_prefix};
+
+	/// A storage directory of its own for each test, removed afterwards.
+	struct TempDir(PathBuf);
+
+	impl TempDir {
+		fn new(name: &str) -> Self {
+			let path = std::env::temp_dir().join(format!("orbit-{name}-{}", std::process::id()));
+			let _ = std::fs::remove_dir_all(&path);
+
+			Self(path)
+		}
+
+		fn storage(&self) -> LocalStorage {
+			LocalStorage::new(self.0.clone(), "cdn/".into(), "http://localhost".into())
+		}
+	}
+
+	impl Drop for TempDir {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_dir_all(&self.0);
+		}
+	}
+
+	async fn listed(storage: &LocalStorage, prefix: &str) -> Vec<String> {
+		let mut keys = storage.list_prefix(prefix).await.unwrap();
+		keys.sort();
+
+		keys
+	}
+
+	#[tokio::test]
+	async fn puts_files_under_prefix() {
+		let dir = TempDir::new("put");
+		let storage = dir.storage();
+
+		storage
+			.put("emotes/1/2/1x.webp", b"webp".to_vec(), "image/webp")
+			.await
+			.unwrap();
+
+		assert_eq!(
+			std::fs::read(dir.0.join("cdn/emotes/1/2/1x.webp")).unwrap(),
+			b"webp"
+		);
+		assert_eq!(
+			storage.url("emotes/1/2/1x.webp"),
+			"http://localhost/cdn/emotes/1/2/1x.webp"
+		);
+		assert!(storage
+			.put("../1x.webp", vec![], "image/webp")
+			.await
+			.is_err());
+	}
+
+	#[tokio::test]
+	async fn gets_stored_files() {
+		let dir = TempDir::new("get");
+		let storage = dir.storage();
+
+		storage
+			.put("emotes/1/2/1x.webp", b"webp".to_vec(), "image/webp")
+			.await
+			.unwrap();
+
+		assert_eq!(
+			storage.get("emotes/1/2/1x.webp").await.unwrap().as_deref(),
+			Some(&b"webp"[..])
+		);
+		assert_eq!(storage.get("emotes/1/2/2x.webp").await.unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn deletes_versions_separately() {
+		let dir = TempDir::new("versions");
+		let storage = dir.storage();
+
+		for key in [
+			"emotes/1/10/1x.webp",
+			"emotes/1/10/1x.png",
+			"emotes/1/11/1x.webp",
+			"emotes/12/13/1x.webp",
+		] {
+			storage.put(key, vec![], "image/webp").await.unwrap();
+		}
+
+		assert_eq!(
+			listed(&storage, &version_prefix(1, 10)).await,
+			["emotes/1/10/1x.png", "emotes/1/10/1x.webp"]
+		);
+
+		storage.delete_prefix(&version_prefix(1, 10)).await.unwrap();
+
+		assert_eq!(
+			listed(&storage, &emote_prefix(1)).await,
+			["emotes/1/11/1x.webp"]
+		);
+
+		// `emotes/1/` mustn't match the files of emote 12.
+		storage.delete_prefix(&emote_prefix(1)).await.unwrap();
+
+		assert!(listed(&storage, &emote_prefix(1)).await.is_empty());
+		assert_eq!(
+			listed(&storage, &emote_prefix(12)).await,
+			["emotes/12/13/1x.webp"]
+		);
+	}
+}
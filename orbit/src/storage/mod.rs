@@ -0,0 +1,52 @@
+use std::fmt::Debug;
+
+use crate::error::Error;
+use crate::Result;
+
+mod local;
+mod s3;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+/// Where emote files are kept. Keys are relative to the configured key prefix,
+/// which each backend applies itself.
+#[axum::async_trait]
+pub trait Storage: Send + Sync {
+	async fn put(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<()>;
+
+	/// `None` if nothing is stored under `key`. Nothing in the API reads files
+	/// back yet, but every backend supports it.
+	#[allow(dead_code)]
+	async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+	async fn delete(&self, keys: &[String]) -> Result<()>;
+
+	async fn list_prefix(&self, prefix: &str) -> Result<Vec<String>>;
+
+	async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+		let keys = self.list_prefix(prefix).await?;
+
+		self.delete(&keys).await
+	}
+
+	/// The public URL a stored key is served from.
+	fn url(&self, key: &str) -> String;
+}
+
+/// Every file belonging to an emote lives under `emotes/{id}/`.
+pub fn emote_prefix(emote_id: i64) -> String {
+	format!("emotes/{emote_id}/")
+}
+
+/// Each version's renditions are kept together so they can be removed on their
+/// own.
+pub fn version_prefix(emote_id: i64, version_id: i64) -> String {
+	format!("{}{version_id}/", emote_prefix(emote_id))
+}
+
+fn storage_error(err: impl Debug) -> Error {
+	tracing::error!(?err);
+
+	Error::Cdn
+}
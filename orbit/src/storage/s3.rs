@@ -0,0 +1,126 @@
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use aws_sdk_s3::Client;
+
+use super::{storage_error, Storage};
+use crate::Result;
+
+/// `DeleteObjects` accepts at most this many keys per request.
+const MAX_DELETE_KEYS: usize = 1000;
+
+pub struct S3Storage {
+	client: Client,
+	bucket: String,
+	prefix: String,
+	url: String,
+}
+
+impl S3Storage {
+	pub fn new(client: Client, bucket: String, prefix: String, url: String) -> Self {
+		Self {
+			client,
+			bucket,
+			prefix,
+			url,
+		}
+	}
+
+	fn key(&self, key: &str) -> String {
+		format!("{}{key}", self.prefix)
+	}
+}
+
+#[axum::async_trait]
+impl Storage for S3Storage {
+	async fn put(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<()> {
+		self.client
+			.put_object()
+			.bucket(&self.bucket)
+			.key(self.key(key))
+			.content_type(content_type)
+			.body(ByteStream::from(body))
+			.send()
+			.await
+			.map_err(storage_error)?;
+
+		Ok(())
+	}
+
+	async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+		let response = self
+			.client
+			.get_object()
+			.bucket(&self.bucket)
+			.key(self.key(key))
+			.send()
+			.await;
+
+		let object = match response {
+			Ok(object) => object,
+			Err(err)
+				if err
+					.as_service_error()
+					.is_some_and(|err| err.is_no_such_key()) =>
+			{
+				return Ok(None)
+			}
+			Err(err) => return Err(storage_error(err)),
+		};
+
+		let body = object.body.collect().await.map_err(storage_error)?;
+
+		Ok(Some(body.into_bytes().to_vec()))
+	}
+
+	async fn delete(&self, keys: &[String]) -> Result<()> {
+		for chunk in keys.chunks(MAX_DELETE_KEYS) {
+			let objects = chunk
+				.iter()
+				.map(|key| ObjectIdentifier::builder().key(self.key(key)).build())
+				.collect::<Result<Vec<_>, _>>()
+				.map_err(storage_error)?;
+
+			self.client
+				.delete_objects()
+				.bucket(&self.bucket)
+				.delete(
+					Delete::builder()
+						.set_objects(Some(objects))
+						.build()
+						.map_err(storage_error)?,
+				)
+				.send()
+				.await
+				.map_err(storage_error)?;
+		}
+
+		Ok(())
+	}
+
+	async fn list_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+		let mut keys = vec![];
+		let mut pages = self
+			.client
+			.list_objects_v2()
+			.bucket(&self.bucket)
+			.prefix(self.key(prefix))
+			.into_paginator()
+			.send();
+
+		while let Some(page) = pages.next().await {
+			let page = page.map_err(storage_error)?;
+
+			for object in page.contents() {
+				if let Some(key) = object.key().and_then(|key| key.strip_prefix(&self.prefix)) {
+					keys.push(key.to_owned());
+				}
+			}
+		}
+
+		Ok(keys)
+	}
+
+	fn url(&self, key: &str) -> String {
+		format!("{}/{}", self.url, self.key(key))
+	}
+}
@@ -0,0 +1,109 @@
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::Result;
+
+const AUTH_URL: &str = "https://id.twitch.tv/oauth2";
+const API_URL: &str = "https://api.twitch.tv/helix";
+
+/// Talks to Twitch for the OAuth login flow.
+///
+/// Both base URLs can be overridden so the flow can be pointed at a local mock
+/// provider.
+pub struct Twitch {
+	http: reqwest::Client,
+	client_id: String,
+	client_secret: String,
+	redirect_uri: String,
+	auth_url: String,
+	api_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwitchUser {
+	pub id: String,
+	pub login: String,
+	pub profile_image_url: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+	access_token: String,
+}
+
+#[derive(Deserialize)]
+struct UsersResponse {
+	data: Vec<TwitchUser>,
+}
+
+impl Twitch {
+	pub fn new(
+		client_id: String,
+		client_secret: String,
+		redirect_uri: String,
+		auth_url: Option<String>,
+		api_url: Option<String>,
+	) -> Self {
+		let trim = |url: String| url.trim_end_matches('/').to_owned();
+
+		Self {
+			http: reqwest::Client::new(),
+			client_id,
+			client_secret,
+			redirect_uri,
+			auth_url: trim(auth_url.unwrap_or_else(|| AUTH_URL.into())),
+			api_url: trim(api_url.unwrap_or_else(|| API_URL.into())),
+		}
+	}
+
+	pub fn authorize_url(&self, state: &str) -> Url {
+		Url::parse_with_params(
+			&format!("{}/authorize", self.auth_url),
+			[
+				("client_id", self.client_id.as_str()),
+				("redirect_uri", self.redirect_uri.as_str()),
+				("response_type", "code"),
+				("scope", ""),
+				("state", state),
+			],
+		)
+		.expect("Invalid Twitch auth URL")
+	}
+
+	/// Exchanges an authorization code for the user it was issued to.
+	pub async fn user_from_code(&self, code: &str) -> Result<TwitchUser> {
+		let token: TokenResponse = self
+			.http
+			.post(format!("{}/token", self.auth_url))
+			.form(&[
+				("client_id", self.client_id.as_str()),
+				("client_secret", self.client_secret.as_str()),
+				("code", code),
+				("grant_type", "authorization_code"),
+				("redirect_uri", self.redirect_uri.as_str()),
+			])
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+
+		let users: UsersResponse = self
+			.http
+			.get(format!("{}/users", self.api_url))
+			.bearer_auth(&token.access_token)
+			.header("Client-Id", &self.client_id)
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+
+		users
+			.data
+			.into_iter()
+			.next()
+			.ok_or_else(|| Error::Twitch("No user returned for access token".into()))
+	}
+}
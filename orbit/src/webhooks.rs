@@ -0,0 +1,565 @@
+//! Sends events to the HTTP endpoints users register for them.
+//!
+//! Only the instance that published an event delivers it, so every webhook is
+//! sent each event once however many instances there are. Each request is
+//! signed with the webhook's secret as
+//! `X-Orbit-Signature: sha256=<hex HMAC of "<timestamp>.<body>">`, with the
+//! timestamp sent as `X-Orbit-Timestamp`, so receivers can check both where it
+//! came from and that it isn't being replayed.
+//!
+//! Webhook URLs are chosen by users, so requests are only ever made to public
+//! addresses. Hosts are checked when a webhook is registered, and again by the
+//! client's resolver on every delivery in case the name has since been pointed
+//! somewhere else.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use orbit_types::models::event::Event;
+use orbit_types::models::webhook::{WebhookEvent, WebhookPayload};
+use orbit_types::Snowflake;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::Url;
+use sha2::Sha256;
+use tokio::sync::{mpsc, Semaphore};
+use tokio_postgres::types::Json as Jsonb;
+
+use crate::db::Pool;
+use crate::error::Error;
+use crate::routes::sets::MAX_INHERITANCE_DEPTH;
+use crate::Result;
+
+const MAX_ATTEMPTS: i32 = 6;
+
+/// Doubled after every failed attempt, so the last one is made a little over
+/// five minutes after the first.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many deliveries can be in flight at once.
+const CONCURRENCY: usize = 32;
+
+/// A webhook is disabled once this many deliveries in a row have failed.
+const MAX_FAILURES: i32 = 10;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long delivery logs are kept.
+const RETENTION: &str = "7 days";
+
+/// Well past the last retry, so a delivery still unfinished by then was cut
+/// short by its instance stopping.
+const ABANDONED_AFTER: &str = "15 minutes";
+
+/// A handle to the delivery worker.
+#[derive(Clone)]
+pub struct Webhooks {
+	sender: mpsc::UnboundedSender<Event>,
+}
+
+struct Target {
+	id: i64,
+	url: String,
+	secret: String,
+}
+
+impl Webhooks {
+	pub fn spawn(pool: Pool) -> Self {
+		let (sender, receiver) = mpsc::unbounded_channel();
+
+		let http = reqwest::Client::builder()
+			.timeout(TIMEOUT)
+			.redirect(reqwest::redirect::Policy::none())
+			.dns_resolver(Arc::new(PublicResolver))
+			.build()
+			.expect("Failed to build webhook HTTP client");
+
+		tokio::spawn(run(pool.clone(), http, receiver));
+		tokio::spawn(prune_deliveries(pool));
+
+		Self { sender }
+	}
+
+	/// Queues an event for every webhook interested in it.
+	pub fn send(&self, event: Event) {
+		if self.sender.send(event).is_err() {
+			tracing::error!("Webhook worker has stopped");
+		}
+	}
+}
+
+/// Whether `ip` is reachable from the internet, as opposed to being one of
+/// this network's own addresses.
+pub fn is_public(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => is_public_v4(ip),
+		IpAddr::V6(ip) => match embedded_v4(ip) {
+			Some(ip) => is_public_v4(ip),
+			None => is_public_v6(ip),
+		},
+	}
+}
+
+/// The IPv4 address that an IPv6 one is translated or tunnelled to, which is
+/// where a request to it ends up.
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+	let [.., a, b, c, d] = ip.octets();
+
+	match ip.segments() {
+		// IPv4-mapped ::ffff:0:0/96, the deprecated IPv4-compatible ::/96 and
+		// NAT64's 64:ff9b::/96 all end in the address.
+		[0, 0, 0, 0, 0, 0xffff, ..] | [0, 0, 0, 0, 0, 0, ..] | [0x64, 0xff9b, 0, 0, 0, 0, ..] => {
+			Some(Ipv4Addr::new(a, b, c, d))
+		}
+		// 6to4's 2002::/16 has it right after the prefix.
+		[0x2002, high, low, ..] => Some(Ipv4Addr::from(u32::from(high) << 16 | u32::from(low))),
+		_ => None,
+	}
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+	let [a, b, ..] = ip.octets();
+
+	!(ip.is_unspecified()
+		|| ip.is_loopback()
+		|| ip.is_private()
+		|| ip.is_link_local()
+		|| ip.is_broadcast()
+		|| ip.is_multicast()
+		|| ip.is_documentation()
+		// 0.0.0.0/8, carrier-grade NAT's 100.64.0.0/10, benchmarking's
+		// 198.18.0.0/15 and the reserved 240.0.0.0/4.
+		|| a == 0
+		|| (a == 100 && b & 0xc0 == 64)
+		|| (a == 198 && b & 0xfe == 18)
+		|| a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+	let first = ip.segments()[0];
+
+	!(ip.is_unspecified()
+		|| ip.is_loopback()
+		|| ip.is_multicast()
+		// Unique local fc00::/7 and link-local fe80::/10.
+		|| first & 0xfe00 == 0xfc00
+		|| first & 0xffc0 == 0xfe80)
+}
+
+/// Whether every address the host of `url` resolves to is public. Hosts that
+/// don't resolve aren't.
+pub async fn is_public_url(url: &Url) -> bool {
+	let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+		return false;
+	};
+
+	if let Some(ip) = host_ip(url) {
+		return is_public(ip);
+	}
+
+	match tokio::net::lookup_host((host, port)).await {
+		Ok(addrs) => {
+			let addrs: Vec<_> = addrs.collect();
+
+			!addrs.is_empty() && addrs.iter().all(|addr| is_public(addr.ip()))
+		}
+		Err(_) => false,
+	}
+}
+
+fn host_ip(url: &Url) -> Option<IpAddr> {
+	// `host_str` keeps the brackets around IPv6 addresses.
+	url.host_str()?
+		.trim_start_matches('[')
+		.trim_end_matches(']')
+		.parse()
+		.ok()
+}
+
+/// Only hands the client public addresses to connect to.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+	fn resolve(&self, name: Name) -> Resolving {
+		Box::pin(async move {
+			let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+				.await?
+				.filter(|addr| is_public(addr.ip()))
+				.collect();
+
+			if addrs.is_empty() {
+				return Err(format!("{} has no public addresses", name.as_str()).into());
+			}
+
+			Ok(Box::new(addrs.into_iter()) as Addrs)
+		})
+	}
+}
+
+async fn run(pool: Pool, http: reqwest::Client, mut receiver: mpsc::UnboundedReceiver<Event>) {
+	let permits = Arc::new(Semaphore::new(CONCURRENCY));
+
+	while let Some(event) = receiver.recv().await {
+		let event = Arc::new(event);
+
+		let targets = match targets(&pool, &event).await {
+			Ok(targets) => targets,
+			Err(err) => {
+				tracing::error!(?err, ?event, "Failed to find webhooks for event");
+				continue;
+			}
+		};
+
+		for target in targets {
+			let pool = pool.clone();
+			let http = http.clone();
+			let event = event.clone();
+			let permits = permits.clone();
+
+			tokio::spawn(async move {
+				if let Err(err) = deliver(&pool, &http, &permits, &target, &event).await {
+					tracing::error!(?err, webhook_id = target.id, "Webhook delivery failed");
+				}
+			});
+		}
+	}
+}
+
+/// The enabled webhooks that want `event`: those of the user it's about, or
+/// for set changes, of every user whose channel set is or inherits from that
+/// set without excluding the emote on the way.
+async fn targets(pool: &Pool, event: &Event) -> Result<Vec<Target>> {
+	let (user_id, set_id, emote_id) = match *event {
+		Event::SetEmoteAdded {
+			set_id, emote_id, ..
+		}
+		| Event::SetEmoteRemoved { set_id, emote_id }
+		| Event::SetEmoteRenamed {
+			set_id, emote_id, ..
+		} => (None, Some(set_id), Some(emote_id)),
+		Event::ChannelSetUpdated { user_id, .. }
+		| Event::EmoteUpdated { user_id, .. }
+		| Event::EmoteDeleted { user_id, .. }
+		| Event::EmoteApproved { user_id, .. }
+		| Event::EmoteRejected { user_id, .. } => (Some(user_id), None, None),
+	};
+
+	let conn = pool.get().await.map_err(|_| Error::Generic)?;
+
+	let targets = conn
+		.query(
+			"
+			WITH RECURSIVE inheriting AS (
+				SELECT id, 0 AS depth, false AS excluded
+				FROM sets
+				WHERE id = $3
+
+				UNION ALL
+
+				SELECT
+					sets.id,
+					inheriting.depth + 1,
+					inheriting.excluded OR EXISTS (
+						SELECT 1
+						FROM set_exclusions AS exclusion
+						WHERE exclusion.set_id = sets.id AND exclusion.emote_id = $4
+					)
+				FROM
+					sets
+					JOIN inheriting ON sets.parent_id = inheriting.id
+				WHERE inheriting.depth < $5
+			)
+			SELECT id, url, secret
+			FROM webhooks
+			WHERE
+				enabled
+				AND $1 = ANY(events)
+				AND (
+					user_id = $2
+					OR user_id IN (
+						SELECT users.id
+						FROM
+							users
+							JOIN inheriting ON inheriting.id = users.channel_set_id
+						WHERE NOT inheriting.excluded
+					)
+				)
+			",
+			&[
+				&WebhookEvent::from(event),
+				&user_id,
+				&set_id,
+				&emote_id,
+				&MAX_INHERITANCE_DEPTH,
+			],
+		)
+		.await?
+		.into_iter()
+		.map(|row| Target {
+			id: row.get("id"),
+			url: row.get("url"),
+			secret: row.get("secret"),
+		})
+		.collect();
+
+	Ok(targets)
+}
+
+async fn deliver(
+	pool: &Pool,
+	http: &reqwest::Client,
+	permits: &Semaphore,
+	target: &Target,
+	event: &Event,
+) -> Result<()> {
+	let payload = WebhookPayload {
+		id: Snowflake::new().0,
+		webhook_id: target.id,
+		event,
+	};
+	let body = serde_json::to_string(&payload)?;
+	let kind = WebhookEvent::from(event);
+
+	pool.get()
+		.await
+		.map_err(|_| Error::Generic)?
+		.execute(
+			"
+			INSERT INTO webhook_deliveries (id, webhook_id, event, payload)
+			VALUES ($1, $2, $3, $4)
+			",
+			&[&payload.id, &target.id, &kind, &Jsonb(&payload)],
+		)
+		.await?;
+
+	let mut delay = RETRY_DELAY;
+
+	for attempt in 1..=MAX_ATTEMPTS {
+		let result = {
+			let _permit = permits.acquire().await.expect("Semaphore is never closed");
+
+			attempt_delivery(http, target, kind, &body).await
+		};
+
+		let success = result.is_ok();
+		let (status_code, error) = match result {
+			Ok(status) => (Some(status), None),
+			Err((status, error)) => (status, Some(error)),
+		};
+		let done = success || attempt == MAX_ATTEMPTS;
+
+		let conn = pool.get().await.map_err(|_| Error::Generic)?;
+
+		conn.execute(
+			"
+			UPDATE webhook_deliveries
+			SET
+				attempts = $2,
+				status_code = $3,
+				error = $4,
+				success = CASE WHEN $5 THEN $6 END,
+				completed_at = CASE WHEN $5 THEN now() END
+			WHERE id = $1
+			",
+			&[&payload.id, &attempt, &status_code, &error, &done, &success],
+		)
+		.await?;
+
+		if success {
+			conn.execute(
+				"UPDATE webhooks SET failures = 0 WHERE id = $1",
+				&[&target.id],
+			)
+			.await?;
+
+			return Ok(());
+		}
+
+		if done {
+			break;
+		}
+
+		drop(conn);
+		tokio::time::sleep(delay).await;
+		delay *= 2;
+	}
+
+	let disabled: bool = pool
+		.get()
+		.await
+		.map_err(|_| Error::Generic)?
+		.query_one(
+			"
+			UPDATE webhooks
+			SET
+				failures = failures + 1,
+				enabled = enabled AND failures + 1 < $2
+			WHERE id = $1
+			RETURNING failures = $2 AS disabled
+			",
+			&[&target.id, &MAX_FAILURES],
+		)
+		.await?
+		.get(0);
+
+	if disabled {
+		tracing::info!(webhook_id = target.id, "Disabled failing webhook");
+	}
+
+	Ok(())
+}
+
+/// Returns the response's status code, or what went wrong along with the status
+/// code if there was one. Responses are never read, so nothing the endpoint
+/// sends back ends up in the delivery log.
+async fn attempt_delivery(
+	http: &reqwest::Client,
+	target: &Target,
+	kind: WebhookEvent,
+	body: &str,
+) -> Result<i32, (Option<i32>, String)> {
+	// Addresses written into the URL never reach the resolver.
+	let url = Url::parse(&target.url).map_err(|err| (None, err.to_string()))?;
+
+	if host_ip(&url).is_some_and(|ip| !is_public(ip)) {
+		return Err((None, "URL does not point to a public address".into()));
+	}
+
+	let timestamp = Utc::now().timestamp().to_string();
+
+	let mut mac = Hmac::<Sha256>::new_from_slice(target.secret.as_bytes())
+		.expect("HMAC accepts any key length");
+	mac.update(timestamp.as_bytes());
+	mac.update(b".");
+	mac.update(body.as_bytes());
+
+	let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+	let response = http
+		.post(url)
+		.header(reqwest::header::CONTENT_TYPE, "application/json")
+		.header("X-Orbit-Event", kind.to_string())
+		.header("X-Orbit-Timestamp", timestamp)
+		.header("X-Orbit-Signature", signature)
+		.body(body.to_owned())
+		.send()
+		.await
+		.map_err(|err| (None, err.to_string()))?;
+
+	let status = response.status();
+
+	if status.is_success() {
+		return Ok(i32::from(status.as_u16()));
+	}
+
+	Err((
+		Some(i32::from(status.as_u16())),
+		format!("Endpoint responded with {status}"),
+	))
+}
+
+/// Also fails deliveries that an instance stopped part way through, starting
+/// with any left over from before this one started.
+async fn prune_deliveries(pool: Pool) {
+	let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+	loop {
+		interval.tick().await;
+
+		let result = async {
+			let conn = pool.get().await.map_err(|_| Error::Generic)?;
+
+			let abandoned = conn
+				.execute(
+					&format!(
+						"
+						UPDATE webhook_deliveries
+						SET
+							success = false,
+							error = 'Delivery was interrupted',
+							completed_at = now()
+						WHERE
+							success IS NULL
+							AND created_at < now() - interval '{ABANDONED_AFTER}'
+						"
+					),
+					&[],
+				)
+				.await?;
+
+			let pruned = conn
+				.execute(
+					&format!(
+						"DELETE FROM webhook_deliveries WHERE created_at < now() - interval '{RETENTION}'"
+					),
+					&[],
+				)
+				.await?;
+
+			Ok::<_, Error>((abandoned, pruned))
+		}
+		.await;
+
+		match result {
+			Ok((abandoned, pruned)) => {
+				if abandoned > 0 {
+					tracing::warn!(count = abandoned, "Failed interrupted webhook deliveries");
+				}
+
+				if pruned > 0 {
+					tracing::debug!(count = pruned, "Pruned old webhook deliveries");
+				}
+			}
+			Err(err) => tracing::error!(?err, "Failed to prune old webhook deliveries"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn public(ip: &str) -> bool {
+		is_public(ip.parse().unwrap())
+	}
+
+	#[test]
+	fn rejects_internal_addresses() {
+		for ip in [
+			"127.0.0.1",
+			"10.1.2.3",
+			"100.64.0.1",
+			"169.254.169.254",
+			"198.19.255.255",
+			"240.0.0.1",
+			"::1",
+			"fd00::1",
+			"fe80::1",
+			"::ffff:127.0.0.1",
+			"::10.0.0.1",
+			"64:ff9b::a9fe:a9fe",
+			"2002:c0a8:0101::1",
+		] {
+			assert!(!public(ip), "{ip} should not be public");
+		}
+	}
+
+	#[test]
+	fn accepts_public_addresses() {
+		for ip in [
+			"1.1.1.1",
+			"198.20.0.1",
+			"2606:4700::1111",
+			"::ffff:1.1.1.1",
+			"64:ff9b::101:101",
+			"2002:101:101::1",
+		] {
+			assert!(public(ip), "{ip} should be public");
+		}
+	}
+}
@@ -0,0 +1,479 @@
+use std::io::Cursor;
+use std::time::Duration;
+
+use image::codecs::jpeg::JpegDecoder;
+use image::codecs::png::PngDecoder;
+use image::metadata::LoopCount;
+use image::{AnimationDecoder, DynamicImage, ImageDecoder, Rgba, RgbaImage};
+
+use crate::{Error, ImageFormat, Result};
+
+/// Upper bounds enforced while decoding untrusted uploads.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+	pub max_size: usize,
+	pub max_width: u32,
+	pub max_height: u32,
+	pub max_frames: usize,
+
+	/// Every frame is kept fully composited, so this bounds the memory a
+	/// decoded image takes up to four bytes per pixel across all of them.
+	pub max_pixels: u64,
+}
+
+impl Default for Limits {
+	fn default() -> Self {
+		Self {
+			max_size: 7 * 1024 * 1024,
+			max_width: 1000,
+			max_height: 1000,
+			max_frames: 1000,
+			max_pixels: 64 * 1024 * 1024,
+		}
+	}
+}
+
+/// What was actually uploaded, as opposed to what the client said it was.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageInfo {
+	pub format: ImageFormat,
+	pub width: u32,
+	pub height: u32,
+	pub frame_count: usize,
+	pub animated: bool,
+	pub size: usize,
+}
+
+/// How many times an animation plays after the first time through, using the
+/// same convention as the GIF `NETSCAPE2.0` extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+	Infinite,
+	Finite(u16),
+}
+
+/// A fully composited frame: every frame covers the whole canvas, with any
+/// disposal of the previous frame already applied.
+#[derive(Debug, Clone)]
+pub struct Frame {
+	pub buffer: RgbaImage,
+	pub delay: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct Image {
+	pub info: ImageInfo,
+	pub frames: Vec<Frame>,
+	pub repeat: Repeat,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Image> {
+	decode_with_limits(bytes, Limits::default())
+}
+
+pub fn decode_with_limits(bytes: &[u8], limits: Limits) -> Result<Image> {
+	if bytes.is_empty() {
+		return Err(Error::Empty);
+	}
+
+	if bytes.len() > limits.max_size {
+		return Err(Error::TooLarge {
+			size: bytes.len(),
+			max: limits.max_size,
+		});
+	}
+
+	let format = ImageFormat::sniff(bytes).ok_or(Error::UnsupportedFormat)?;
+	let reader = Cursor::new(bytes);
+
+	// Dimensions are checked against the header before any pixel data is
+	// decoded so oversized uploads are rejected cheaply.
+	let (frames, repeat) = match format {
+		ImageFormat::Png => {
+			let decoder = PngDecoder::new(reader)?;
+			check_dimensions(decoder.dimensions(), &limits)?;
+
+			if decoder.is_apng()? {
+				let dimensions = decoder.dimensions();
+				let decoder = decoder.apng()?;
+
+				// APNG counts total plays where GIF counts repeats after the
+				// first.
+				let repeat = match decoder.loop_count() {
+					LoopCount::Infinite => Repeat::Infinite,
+					LoopCount::Finite(plays) => {
+						Repeat::Finite(u16::try_from(plays.get() - 1).unwrap_or(u16::MAX))
+					}
+				};
+
+				(collect_frames(decoder, dimensions, &limits)?, repeat)
+			} else {
+				(vec![still_frame(decoder)?], Repeat::Infinite)
+			}
+		}
+		ImageFormat::Gif => decode_gif(reader, &limits)?,
+		ImageFormat::WebP => decode_webp(reader, &limits)?,
+		ImageFormat::Jpeg => {
+			let decoder = JpegDecoder::new(reader)?;
+			check_dimensions(decoder.dimensions(), &limits)?;
+
+			(vec![still_frame(decoder)?], Repeat::Infinite)
+		}
+	};
+
+	let (width, height) = frames[0].buffer.dimensions();
+
+	Ok(Image {
+		info: ImageInfo {
+			format,
+			width,
+			height,
+			frame_count: frames.len(),
+			animated: frames.len() > 1,
+			size: bytes.len(),
+		},
+		frames,
+		repeat,
+	})
+}
+
+fn check_dimensions((width, height): (u32, u32), limits: &Limits) -> Result<()> {
+	if width == 0 || height == 0 {
+		return Err(Error::ZeroDimensions);
+	}
+
+	if width > limits.max_width || height > limits.max_height {
+		return Err(Error::DimensionsTooLarge {
+			width,
+			height,
+			max_width: limits.max_width,
+			max_height: limits.max_height,
+		});
+	}
+
+	Ok(())
+}
+
+/// Checked before each frame is kept, with `count` including it.
+fn check_frames(count: usize, (width, height): (u32, u32), limits: &Limits) -> Result<()> {
+	if count > limits.max_frames {
+		return Err(Error::TooManyFrames {
+			max: limits.max_frames,
+		});
+	}
+
+	if count as u64 * u64::from(width) * u64::from(height) > limits.max_pixels {
+		return Err(Error::TooManyPixels {
+			max: limits.max_pixels,
+		});
+	}
+
+	Ok(())
+}
+
+// Browsers play GIF frames with a delay of 10ms or less at 100ms, so the
+// stored delay is normalised to match what the uploader saw. Other formats are
+// played as written.
+fn gif_frame_delay(centiseconds: u16) -> Duration {
+	if centiseconds <= 1 {
+		Duration::from_millis(100)
+	} else {
+		Duration::from_millis(u64::from(centiseconds) * 10)
+	}
+}
+
+fn still_frame(decoder: impl ImageDecoder) -> Result<Frame> {
+	Ok(Frame {
+		buffer: DynamicImage::from_decoder(decoder)?.into_rgba8(),
+		delay: Duration::ZERO,
+	})
+}
+
+fn collect_frames<'a>(
+	decoder: impl AnimationDecoder<'a>,
+	dimensions: (u32, u32),
+	limits: &Limits,
+) -> Result<Vec<Frame>> {
+	let mut frames = vec![];
+
+	// Frames come out composited onto a full canvas, so checking each one as it
+	// arrives keeps at most one frame beyond the limits in memory.
+	for frame in decoder.into_frames() {
+		check_frames(frames.len() + 1, dimensions, limits)?;
+
+		let frame = frame?;
+		let (numer, denom) = frame.delay().numer_denom_ms();
+
+		frames.push(Frame {
+			delay: Duration::from_millis(u64::from(numer) / u64::from(denom.max(1))),
+			buffer: frame.into_buffer(),
+		});
+	}
+
+	if frames.is_empty() {
+		return Err(Error::Empty);
+	}
+
+	Ok(frames)
+}
+
+fn decode_gif(reader: Cursor<&[u8]>, limits: &Limits) -> Result<(Vec<Frame>, Repeat)> {
+	let mut options = gif::DecodeOptions::new();
+	options.set_color_output(gif::ColorOutput::RGBA);
+
+	// Frames larger than the logical screen would otherwise be decoded at
+	// whatever size they claim to be, regardless of the dimension limits.
+	options.check_frame_consistency(true);
+
+	let mut decoder = options.read_info(reader)?;
+	let (width, height) = (u32::from(decoder.width()), u32::from(decoder.height()));
+	check_dimensions((width, height), limits)?;
+
+	let mut canvas = RgbaImage::new(width, height);
+	let mut frames = vec![];
+
+	while let Some(frame) = decoder.read_next_frame()? {
+		// Checked before this frame's copy of the canvas is made.
+		check_frames(frames.len() + 1, (width, height), limits)?;
+
+		let previous = (frame.dispose == gif::DisposalMethod::Previous).then(|| canvas.clone());
+		let (left, top) = (u32::from(frame.left), u32::from(frame.top));
+
+		// Frames may be smaller than the logical screen and only overwrite the
+		// pixels they don't mark as transparent.
+		for (i, pixel) in frame.buffer.chunks_exact(4).enumerate() {
+			let x = left + i as u32 % u32::from(frame.width);
+			let y = top + i as u32 / u32::from(frame.width);
+
+			if pixel[3] != 0 && x < width && y < height {
+				canvas.put_pixel(x, y, Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]));
+			}
+		}
+
+		frames.push(Frame {
+			buffer: canvas.clone(),
+			delay: gif_frame_delay(frame.delay),
+		});
+
+		match frame.dispose {
+			gif::DisposalMethod::Background => {
+				let right = (left + u32::from(frame.width)).min(width);
+				let bottom = (top + u32::from(frame.height)).min(height);
+
+				for y in top..bottom {
+					for x in left..right {
+						canvas.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+					}
+				}
+			}
+			gif::DisposalMethod::Previous => {
+				if let Some(previous) = previous {
+					canvas = previous;
+				}
+			}
+			gif::DisposalMethod::Any | gif::DisposalMethod::Keep => (),
+		}
+	}
+
+	if frames.is_empty() {
+		return Err(Error::Empty);
+	}
+
+	let repeat = match decoder.repeat() {
+		gif::Repeat::Infinite => Repeat::Infinite,
+		gif::Repeat::Finite(count) => Repeat::Finite(count),
+	};
+
+	Ok((frames, repeat))
+}
+
+fn decode_webp(reader: Cursor<&[u8]>, limits: &Limits) -> Result<(Vec<Frame>, Repeat)> {
+	let mut decoder = image_webp::WebPDecoder::new(reader)?;
+	let (width, height) = decoder.dimensions();
+	check_dimensions((width, height), limits)?;
+
+	let mut buffer = vec![0; decoder.output_buffer_size().ok_or(Error::ZeroDimensions)?];
+	let into_rgba = |buffer: &[u8], has_alpha: bool| -> Result<RgbaImage> {
+		let image = if has_alpha {
+			RgbaImage::from_raw(width, height, buffer.to_vec())
+		} else {
+			image::RgbImage::from_raw(width, height, buffer.to_vec())
+				.map(|image| DynamicImage::ImageRgb8(image).into_rgba8())
+		};
+
+		image.ok_or(Error::Empty)
+	};
+
+	if !decoder.is_animated() {
+		decoder.read_image(&mut buffer)?;
+
+		let frame = Frame {
+			buffer: into_rgba(&buffer, decoder.has_alpha())?,
+			delay: Duration::ZERO,
+		};
+
+		return Ok((vec![frame], Repeat::Infinite));
+	}
+
+	check_frames(decoder.num_frames() as usize, (width, height), limits)?;
+
+	let mut frames = vec![];
+
+	for _ in 0..decoder.num_frames() {
+		let delay = decoder.read_frame(&mut buffer)?;
+
+		frames.push(Frame {
+			buffer: into_rgba(&buffer, decoder.has_alpha())?,
+			delay: Duration::from_millis(u64::from(delay)),
+		});
+	}
+
+	if frames.is_empty() {
+		return Err(Error::Empty);
+	}
+
+	// WebP counts total plays where GIF counts repeats after the first.
+	let repeat = match decoder.loop_count() {
+		image_webp::LoopCount::Forever => Repeat::Infinite,
+		image_webp::LoopCount::Times(plays) => Repeat::Finite(plays.get() - 1),
+	};
+
+	Ok((frames, repeat))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures;
+
+	#[test]
+	fn describes_still_images() {
+		let bytes = fixtures::png(30, 20);
+		let image = decode(&bytes).unwrap();
+
+		assert_eq!(image.info.format, ImageFormat::Png);
+		assert_eq!((image.info.width, image.info.height), (30, 20));
+		assert_eq!(image.info.frame_count, 1);
+		assert!(!image.info.animated);
+		assert_eq!(image.info.size, bytes.len());
+		assert_eq!(image.frames[0].buffer.get_pixel(0, 0), &fixtures::color(0));
+	}
+
+	#[test]
+	fn describes_animations() {
+		let bytes = fixtures::gif(16, 8, &[5, 5, 5], gif::Repeat::Infinite);
+		let image = decode(&bytes).unwrap();
+
+		assert_eq!(image.info.format, ImageFormat::Gif);
+		assert_eq!((image.info.width, image.info.height), (16, 8));
+		assert_eq!(image.info.frame_count, 3);
+		assert!(image.info.animated);
+
+		for (i, frame) in image.frames.iter().enumerate() {
+			assert_eq!(frame.buffer.dimensions(), (16, 8));
+			assert_eq!(frame.buffer.get_pixel(0, 0), &fixtures::color(i));
+		}
+	}
+
+	#[test]
+	fn normalises_short_gif_delays() {
+		let bytes = fixtures::gif(4, 4, &[0, 1, 2, 7], gif::Repeat::Finite(1));
+		let image = decode(&bytes).unwrap();
+		let delays: Vec<_> = image.frames.iter().map(|frame| frame.delay).collect();
+
+		assert_eq!(delays, [100, 100, 20, 70].map(Duration::from_millis));
+		assert_eq!(image.repeat, Repeat::Finite(1));
+	}
+
+	#[test]
+	fn keeps_apng_timing_and_loops() {
+		let bytes = fixtures::apng(4, 4, &[5, 40, 120], 3);
+		let image = decode(&bytes).unwrap();
+		let delays: Vec<_> = image.frames.iter().map(|frame| frame.delay).collect();
+
+		assert_eq!(image.info.format, ImageFormat::Png);
+		assert_eq!(image.info.frame_count, 3);
+		assert_eq!(delays, [5, 40, 120].map(Duration::from_millis));
+		assert_eq!(image.repeat, Repeat::Finite(2));
+
+		let forever = decode(&fixtures::apng(4, 4, &[50, 50], 0)).unwrap();
+
+		assert_eq!(forever.repeat, Repeat::Infinite);
+	}
+
+	#[test]
+	fn rejects_what_isnt_an_image() {
+		assert!(matches!(decode(b""), Err(Error::Empty)));
+		assert!(matches!(
+			decode(b"<svg></svg>"),
+			Err(Error::UnsupportedFormat)
+		));
+		assert!(matches!(
+			decode(b"\x89PNG\r\n\x1a\nnot really"),
+			Err(Error::Decode(_))
+		));
+	}
+
+	#[test]
+	fn limits_size() {
+		let bytes = fixtures::png(10, 10);
+		let max_size = bytes.len() - 1;
+
+		assert!(matches!(
+			decode_with_limits(
+				&bytes,
+				Limits {
+					max_size,
+					..Limits::default()
+				}
+			),
+			Err(Error::TooLarge { max, .. }) if max == max_size
+		));
+	}
+
+	#[test]
+	fn limits_dimensions() {
+		assert!(matches!(
+			decode(&fixtures::png(1001, 10)),
+			Err(Error::DimensionsTooLarge {
+				width: 1001,
+				height: 10,
+				..
+			})
+		));
+		assert!(matches!(
+			decode(&fixtures::gif(10, 1001, &[5], gif::Repeat::Infinite)),
+			Err(Error::DimensionsTooLarge { .. })
+		));
+	}
+
+	#[test]
+	fn limits_frames() {
+		let bytes = fixtures::gif(4, 4, &[5; 3], gif::Repeat::Infinite);
+		let max_frames = |max_frames| Limits {
+			max_frames,
+			..Limits::default()
+		};
+
+		assert!(decode_with_limits(&bytes, max_frames(3)).is_ok());
+		assert!(matches!(
+			decode_with_limits(&bytes, max_frames(2)),
+			Err(Error::TooManyFrames { max: 2 })
+		));
+	}
+
+	#[test]
+	fn limits_pixels_across_frames() {
+		let bytes = fixtures::gif(10, 10, &[5; 5], gif::Repeat::Infinite);
+		let max_pixels = |max_pixels| Limits {
+			max_pixels,
+			..Limits::default()
+		};
+
+		assert!(decode_with_limits(&bytes, max_pixels(500)).is_ok());
+		assert!(matches!(
+			decode_with_limits(&bytes, max_pixels(499)),
+			Err(Error::TooManyPixels { max: 499 })
+		));
+	}
+}
@@ -0,0 +1,198 @@
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{ExtendedColorType, ImageEncoder};
+
+use crate::{webp, Error, Image, ImageFormat, Rendition, Repeat, Result};
+
+/// A rendition encoded into a file ready to be stored.
+#[derive(Debug, Clone)]
+pub struct File {
+	pub scale: u32,
+	pub width: u32,
+	pub height: u32,
+	pub format: ImageFormat,
+	pub bytes: Vec<u8>,
+
+	/// Whether this is the still preview of an animated emote.
+	pub is_static: bool,
+}
+
+impl File {
+	/// The file name within an emote's directory, e.g. `2x.webp` or
+	/// `2x_static.png`.
+	pub fn name(&self) -> String {
+		let suffix = if self.is_static { "_static" } else { "" };
+
+		format!("{}x{suffix}.{}", self.scale, self.format.extension())
+	}
+}
+
+/// Formats every rendition is encoded to. Animated emotes get a GIF in place of
+/// the PNG for clients without animated WebP support.
+pub fn output_formats(animated: bool) -> [ImageFormat; 2] {
+	if animated {
+		[ImageFormat::WebP, ImageFormat::Gif]
+	} else {
+		[ImageFormat::WebP, ImageFormat::Png]
+	}
+}
+
+pub fn encode(rendition: &Rendition, format: ImageFormat) -> Result<File> {
+	let animated = rendition.frames.len() > 1;
+
+	let bytes = match format {
+		ImageFormat::WebP if animated => {
+			webp::encode_animation(&rendition.frames, rendition.repeat)?
+		}
+		ImageFormat::WebP => webp::encode_still(&rendition.frames[0].buffer)?,
+		ImageFormat::Gif => encode_gif(rendition)?,
+		ImageFormat::Png => {
+			let mut bytes = vec![];
+
+			PngEncoder::new_with_quality(&mut bytes, CompressionType::Best, FilterType::Adaptive)
+				.write_image(
+					&rendition.frames[0].buffer,
+					rendition.width,
+					rendition.height,
+					ExtendedColorType::Rgba8,
+				)
+				.map_err(|err| Error::Encode(err.to_string()))?;
+
+			bytes
+		}
+		_ => return Err(Error::Encode(format!("{format:?} output is not supported"))),
+	};
+
+	Ok(File {
+		scale: rendition.scale,
+		width: rendition.width,
+		height: rendition.height,
+		format,
+		bytes,
+		is_static: false,
+	})
+}
+
+/// Generates the full rendition ladder for `image` in every output format,
+/// plus a still preview of each size for animated emotes.
+pub fn process(image: &Image) -> Result<Vec<File>> {
+	let mut files = vec![];
+
+	for rendition in crate::renditions(image)? {
+		for format in output_formats(image.info.animated) {
+			files.push(encode(&rendition, format)?);
+		}
+	}
+
+	if image.info.animated {
+		for rendition in crate::renditions(&crate::preview(image))? {
+			for format in output_formats(false) {
+				files.push(File {
+					is_static: true,
+					..encode(&rendition, format)?
+				});
+			}
+		}
+	}
+
+	Ok(files)
+}
+
+fn encode_gif(rendition: &Rendition) -> Result<Vec<u8>> {
+	let too_wide = |_| Error::Encode("GIF dimensions are out of range".into());
+	let width = u16::try_from(rendition.width).map_err(too_wide)?;
+	let height = u16::try_from(rendition.height).map_err(too_wide)?;
+
+	let mut bytes = vec![];
+	let mut encoder = gif::Encoder::new(&mut bytes, width, height, &[])
+		.map_err(|err| Error::Encode(err.to_string()))?;
+
+	let repeat = match rendition.repeat {
+		Repeat::Infinite => gif::Repeat::Infinite,
+		Repeat::Finite(count) => gif::Repeat::Finite(count),
+	};
+
+	encoder
+		.set_repeat(repeat)
+		.map_err(|err| Error::Encode(err.to_string()))?;
+
+	for frame in &rendition.frames {
+		let mut pixels = frame.buffer.as_raw().clone();
+		let mut gif_frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+
+		// Frames are stored composited, so each one replaces the last entirely.
+		gif_frame.delay = (frame.delay.as_millis() / 10).clamp(2, u128::from(u16::MAX)) as u16;
+		gif_frame.dispose = gif::DisposalMethod::Background;
+
+		encoder
+			.write_frame(&gif_frame)
+			.map_err(|err| Error::Encode(err.to_string()))?;
+	}
+
+	drop(encoder);
+
+	Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use image::RgbaImage;
+
+	use super::*;
+	use crate::fixtures;
+
+	#[test]
+	fn encodes_every_size_and_format() {
+		let image = crate::decode(&fixtures::png(100, 50)).unwrap();
+		let names: Vec<_> = process(&image).unwrap().iter().map(File::name).collect();
+
+		assert_eq!(
+			names,
+			["1x.webp", "1x.png", "2x.webp", "2x.png", "3x.webp", "3x.png", "4x.webp", "4x.png"]
+		);
+	}
+
+	#[test]
+	fn keeps_animation_timing() {
+		let bytes = fixtures::gif(20, 10, &[5, 7, 20], gif::Repeat::Finite(2));
+		let image = crate::decode(&bytes).unwrap();
+		let files = process(&image).unwrap();
+
+		assert_eq!(files.iter().filter(|file| !file.is_static).count(), 8);
+		assert_eq!(files.iter().filter(|file| file.is_static).count(), 8);
+
+		for file in files.iter().filter(|file| !file.is_static) {
+			let output = crate::decode(&file.bytes).unwrap();
+			let delays: Vec<_> = output.frames.iter().map(|frame| frame.delay).collect();
+
+			assert_eq!(output.info.format, file.format);
+			assert_eq!(
+				(output.info.width, output.info.height),
+				(file.width, file.height)
+			);
+			assert_eq!(
+				delays,
+				[50, 70, 200].map(Duration::from_millis),
+				"{}",
+				file.name()
+			);
+			assert_eq!(output.repeat, Repeat::Finite(2), "{}", file.name());
+
+			for (i, frame) in output.frames.iter().enumerate() {
+				let center = frame.buffer.get_pixel(file.width / 2, file.height / 2);
+
+				assert_eq!(center, &fixtures::color(i), "{}", file.name());
+			}
+		}
+	}
+
+	#[test]
+	fn rejects_animations_too_long_to_encode() {
+		let frame = RgbaImage::new(32, 32);
+		let delay = Duration::from_millis(i32::MAX as u64);
+		let image = fixtures::decoded(vec![frame.clone(), frame], delay);
+
+		assert!(matches!(process(&image), Err(Error::Encode(_))));
+	}
+}
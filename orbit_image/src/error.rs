@@ -0,0 +1,60 @@
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Image is empty.")]
+	Empty,
+
+	#[error("Image is too large ({size} bytes, maximum is {max} bytes).")]
+	TooLarge { size: usize, max: usize },
+
+	#[error("Unsupported image format.")]
+	UnsupportedFormat,
+
+	#[error(
+		"Image dimensions are too large ({width}x{height}, maximum is {max_width}x{max_height})."
+	)]
+	DimensionsTooLarge {
+		width: u32,
+		height: u32,
+		max_width: u32,
+		max_height: u32,
+	},
+
+	#[error("Image has no visible area.")]
+	ZeroDimensions,
+
+	#[error(
+		"Image is too wide or too tall ({width}x{height}, at most {max}:1 either way).",
+		max = crate::resize::MAX_ASPECT_RATIO
+	)]
+	AspectRatio { width: u32, height: u32 },
+
+	#[error("Image has too many frames (maximum is {max}).")]
+	TooManyFrames { max: usize },
+
+	#[error("Image has too many pixels across all of its frames (maximum is {max}).")]
+	TooManyPixels { max: u64 },
+
+	#[error("Image could not be decoded.")]
+	Decode(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+	#[error("Image could not be encoded: {0}")]
+	Encode(String),
+}
+
+impl From<image::ImageError> for Error {
+	fn from(value: image::ImageError) -> Self {
+		Self::Decode(value.into())
+	}
+}
+
+impl From<gif::DecodingError> for Error {
+	fn from(value: gif::DecodingError) -> Self {
+		Self::Decode(value.into())
+	}
+}
+
+impl From<image_webp::DecodingError> for Error {
+	fn from(value: image_webp::DecodingError) -> Self {
+		Self::Decode(value.into())
+	}
+}
@@ -0,0 +1,92 @@
+//! Small images built in memory for tests.
+
+use std::time::Duration;
+
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder, Rgba, RgbaImage};
+
+use crate::{Frame, Image, ImageFormat, ImageInfo, Repeat};
+
+/// A colour for the `i`th frame that survives GIF's palette intact.
+pub fn color(i: usize) -> Rgba<u8> {
+	Rgba([(i as u8).wrapping_mul(40), 0, 255, 255])
+}
+
+pub fn png(width: u32, height: u32) -> Vec<u8> {
+	let buffer = RgbaImage::from_pixel(width, height, color(0));
+	let mut bytes = vec![];
+
+	PngEncoder::new(&mut bytes)
+		.write_image(&buffer, width, height, ExtendedColorType::Rgba8)
+		.unwrap();
+
+	bytes
+}
+
+/// A GIF with one solid frame per delay, given in hundredths of a second.
+pub fn gif(width: u16, height: u16, delays: &[u16], repeat: gif::Repeat) -> Vec<u8> {
+	let mut bytes = vec![];
+	let mut encoder = gif::Encoder::new(&mut bytes, width, height, &[]).unwrap();
+	encoder.set_repeat(repeat).unwrap();
+
+	for (i, &delay) in delays.iter().enumerate() {
+		let mut pixels = color(i).0.repeat(usize::from(width) * usize::from(height));
+		let mut frame = gif::Frame::from_rgba(width, height, &mut pixels);
+		frame.delay = delay;
+
+		encoder.write_frame(&frame).unwrap();
+	}
+
+	drop(encoder);
+
+	bytes
+}
+
+/// An already decoded image made of `frames`.
+pub fn decoded(frames: Vec<RgbaImage>, delay: Duration) -> Image {
+	let (width, height) = frames[0].dimensions();
+	let animated = frames.len() > 1;
+
+	Image {
+		info: ImageInfo {
+			format: if animated {
+				ImageFormat::Gif
+			} else {
+				ImageFormat::Png
+			},
+			width,
+			height,
+			frame_count: frames.len(),
+			animated,
+			size: 0,
+		},
+		frames: frames
+			.into_iter()
+			.map(|buffer| Frame { buffer, delay })
+			.collect(),
+		repeat: Repeat::Infinite,
+	}
+}
+
+/// An APNG with one solid frame per delay, given in milliseconds. A `plays` of
+/// 0 loops forever.
+pub fn apng(width: u32, height: u32, delays: &[u16], plays: u32) -> Vec<u8> {
+	let mut bytes = vec![];
+	let mut encoder = png::Encoder::new(&mut bytes, width, height);
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	encoder.set_animated(delays.len() as u32, plays).unwrap();
+
+	let mut writer = encoder.write_header().unwrap();
+
+	for (i, &delay) in delays.iter().enumerate() {
+		let pixels = color(i).0.repeat((width * height) as usize);
+
+		writer.set_frame_delay(delay, 1000).unwrap();
+		writer.write_image_data(&pixels).unwrap();
+	}
+
+	writer.finish().unwrap();
+
+	bytes
+}
@@ -0,0 +1,73 @@
+/// A container format accepted for emote uploads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+	Png,
+	Gif,
+	WebP,
+	Jpeg,
+}
+
+impl ImageFormat {
+	/// Detects the format from the leading magic bytes, ignoring whatever the
+	/// client claims the file is.
+	pub fn sniff(bytes: &[u8]) -> Option<Self> {
+		if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+			Some(Self::Png)
+		} else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+			Some(Self::Gif)
+		} else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+			Some(Self::WebP)
+		} else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+			Some(Self::Jpeg)
+		} else {
+			None
+		}
+	}
+
+	pub fn extension(self) -> &'static str {
+		match self {
+			Self::Png => "png",
+			Self::Gif => "gif",
+			Self::WebP => "webp",
+			Self::Jpeg => "jpg",
+		}
+	}
+
+	pub fn mime_type(self) -> &'static str {
+		match self {
+			Self::Png => "image/png",
+			Self::Gif => "image/gif",
+			Self::WebP => "image/webp",
+			Self::Jpeg => "image/jpeg",
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sniffs_magic_bytes() {
+		assert_eq!(
+			ImageFormat::sniff(b"\x89PNG\r\n\x1a\n...."),
+			Some(ImageFormat::Png)
+		);
+		assert_eq!(ImageFormat::sniff(b"GIF89a...."), Some(ImageFormat::Gif));
+		assert_eq!(
+			ImageFormat::sniff(b"RIFF\0\0\0\0WEBPVP8 "),
+			Some(ImageFormat::WebP)
+		);
+		assert_eq!(
+			ImageFormat::sniff(&[0xff, 0xd8, 0xff, 0xe0]),
+			Some(ImageFormat::Jpeg)
+		);
+		// AVIF would need libdav1d to decode, so it isn't accepted.
+		assert_eq!(
+			ImageFormat::sniff(b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf"),
+			None
+		);
+		assert_eq!(ImageFormat::sniff(b"<svg></svg>"), None);
+		assert_eq!(ImageFormat::sniff(b""), None);
+	}
+}
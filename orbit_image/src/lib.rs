@@ -1,14 +1,18 @@
-pub fn add(left: usize, right: usize) -> usize {
-	left + right
-}
-
+mod decode;
+mod encode;
+mod error;
 #[cfg(test)]
-mod tests {
-	use super::*;
+mod fixtures;
+mod format;
+mod preview;
+mod resize;
+mod webp;
+
+pub use decode::{decode, decode_with_limits, Frame, Image, ImageInfo, Limits, Repeat};
+pub use encode::{encode, output_formats, process, File};
+pub use error::Error;
+pub use format::ImageFormat;
+pub use preview::preview;
+pub use resize::{rendition, renditions, Rendition, BASE_HEIGHT, MAX_ASPECT_RATIO, SCALES};
 
-	#[test]
-	fn it_works() {
-		let result = add(2, 2);
-		assert_eq!(result, 4);
-	}
-}
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use crate::{Frame, Image, ImageInfo, Repeat};
+
+/// Picks a single frame to stand in for an animated emote in pickers and for
+/// users who prefer reduced motion.
+///
+/// The first frame is used unless a later one covers more of the canvas, which
+/// skips over animations that fade in from an empty frame.
+pub fn preview(image: &Image) -> Image {
+	let frame = image
+		.frames
+		.iter()
+		.enumerate()
+		.max_by_key(|(i, frame)| (coverage(frame), std::cmp::Reverse(*i)))
+		.map(|(_, frame)| frame)
+		.unwrap_or(&image.frames[0]);
+
+	Image {
+		info: ImageInfo {
+			frame_count: 1,
+			animated: false,
+			..image.info
+		},
+		frames: vec![Frame {
+			buffer: frame.buffer.clone(),
+			delay: Duration::ZERO,
+		}],
+		repeat: Repeat::Infinite,
+	}
+}
+
+fn coverage(frame: &Frame) -> usize {
+	frame.buffer.pixels().filter(|pixel| pixel[3] != 0).count()
+}
+
+#[cfg(test)]
+mod tests {
+	use image::{Rgba, RgbaImage};
+
+	use super::*;
+	use crate::fixtures;
+
+	/// A frame with its first `opaque` pixels drawn in the `i`th colour.
+	fn frame(i: usize, opaque: u32) -> RgbaImage {
+		RgbaImage::from_fn(4, 4, |x, y| {
+			if y * 4 + x < opaque {
+				fixtures::color(i)
+			} else {
+				Rgba([0, 0, 0, 0])
+			}
+		})
+	}
+
+	#[test]
+	fn uses_first_frame_by_default() {
+		let image = fixtures::decoded(
+			vec![frame(0, 16), frame(1, 16), frame(2, 16)],
+			Duration::from_millis(50),
+		);
+		let preview = preview(&image);
+
+		assert_eq!(preview.frames.len(), 1);
+		assert_eq!(preview.frames[0].buffer, image.frames[0].buffer);
+		assert_eq!(preview.frames[0].delay, Duration::ZERO);
+		assert_eq!(preview.info.frame_count, 1);
+		assert!(!preview.info.animated);
+	}
+
+	#[test]
+	fn skips_fade_ins() {
+		let image = fixtures::decoded(
+			vec![frame(0, 0), frame(1, 8), frame(2, 16), frame(3, 16)],
+			Duration::from_millis(50),
+		);
+
+		assert_eq!(preview(&image).frames[0].buffer, image.frames[2].buffer);
+	}
+}
@@ -0,0 +1,156 @@
+use image::imageops::{self, FilterType};
+
+use crate::{Error, Frame, Image, Repeat, Result};
+
+/// Height in pixels of the 1x rendition; every other scale is a multiple of it.
+pub const BASE_HEIGHT: u32 = 28;
+
+/// The scales generated for every emote, served as `1x` through `4x`.
+pub const SCALES: [u32; 4] = [1, 2, 3, 4];
+
+/// How many times wider than it is tall an emote can be, or the other way
+/// around.
+pub const MAX_ASPECT_RATIO: u32 = 4;
+
+/// An emote resized to one rung of the rendition ladder.
+#[derive(Debug, Clone)]
+pub struct Rendition {
+	pub scale: u32,
+	pub width: u32,
+	pub height: u32,
+	pub frames: Vec<Frame>,
+	pub repeat: Repeat,
+}
+
+/// Resizes every frame of `image` to each of [`SCALES`], preserving the aspect
+/// ratio. All frames of an animation are resized to the same dimensions.
+pub fn renditions(image: &Image) -> Result<Vec<Rendition>> {
+	SCALES
+		.iter()
+		.map(|&scale| rendition(image, scale))
+		.collect()
+}
+
+pub fn rendition(image: &Image, scale: u32) -> Result<Rendition> {
+	let (width, height) = (image.info.width, image.info.height);
+
+	if u64::from(width) > u64::from(height) * u64::from(MAX_ASPECT_RATIO)
+		|| u64::from(height) > u64::from(width) * u64::from(MAX_ASPECT_RATIO)
+	{
+		return Err(Error::AspectRatio { width, height });
+	}
+
+	let height = BASE_HEIGHT * scale;
+	let width = scaled_width(width, image.info.height, height)?;
+
+	let frames = image
+		.frames
+		.iter()
+		.map(|frame| Frame {
+			buffer: imageops::resize(&frame.buffer, width, height, FilterType::Lanczos3),
+			delay: frame.delay,
+		})
+		.collect();
+
+	Ok(Rendition {
+		scale,
+		width,
+		height,
+		frames,
+		repeat: image.repeat,
+	})
+}
+
+/// Errors rather than producing a width no output format can hold.
+fn scaled_width(width: u32, height: u32, target_height: u32) -> Result<u32> {
+	let scaled =
+		(u64::from(width) * u64::from(target_height) + u64::from(height) / 2) / u64::from(height);
+	let max = u32::from(u16::MAX);
+
+	match u32::try_from(scaled) {
+		Ok(scaled) if scaled <= max => Ok(scaled.max(1)),
+		_ => Err(Error::DimensionsTooLarge {
+			width: scaled.min(u64::from(u32::MAX)) as u32,
+			height: target_height,
+			max_width: max,
+			max_height: max,
+		}),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use image::RgbaImage;
+
+	use super::*;
+	use crate::fixtures;
+
+	fn sized(width: u32, height: u32, frames: usize) -> Image {
+		let frame = RgbaImage::from_pixel(width, height, fixtures::color(0));
+
+		fixtures::decoded(vec![frame; frames], Duration::from_millis(50))
+	}
+
+	#[test]
+	fn builds_the_ladder() {
+		let renditions = renditions(&sized(100, 50, 1)).unwrap();
+		let sizes: Vec<_> = renditions
+			.iter()
+			.map(|rendition| (rendition.scale, rendition.width, rendition.height))
+			.collect();
+
+		assert_eq!(
+			sizes,
+			[(1, 56, 28), (2, 112, 56), (3, 168, 84), (4, 224, 112)]
+		);
+
+		for rendition in &renditions {
+			assert_eq!(
+				rendition.frames[0].buffer.dimensions(),
+				(rendition.width, rendition.height)
+			);
+		}
+	}
+
+	#[test]
+	fn resizes_every_frame_alike() {
+		let rendition = rendition(&sized(30, 40, 3), 2).unwrap();
+
+		assert_eq!((rendition.width, rendition.height), (42, 56));
+		assert_eq!(rendition.frames.len(), 3);
+
+		for frame in &rendition.frames {
+			assert_eq!(frame.buffer.dimensions(), (42, 56));
+			assert_eq!(frame.delay, Duration::from_millis(50));
+		}
+	}
+
+	#[test]
+	fn limits_aspect_ratio() {
+		assert!(rendition(&sized(400, 100, 1), 1).is_ok());
+		assert!(rendition(&sized(100, 400, 1), 1).is_ok());
+		assert!(matches!(
+			rendition(&sized(401, 100, 1), 1),
+			Err(Error::AspectRatio {
+				width: 401,
+				height: 100
+			})
+		));
+		assert!(matches!(
+			rendition(&sized(100, 401, 1), 1),
+			Err(Error::AspectRatio { .. })
+		));
+	}
+
+	#[test]
+	fn scales_width_to_height() {
+		assert_eq!(scaled_width(100, 50, 28).unwrap(), 56);
+		assert_eq!(scaled_width(3, 1000, 28).unwrap(), 1);
+		assert!(matches!(
+			scaled_width(1000, 1, 112),
+			Err(Error::DimensionsTooLarge { .. })
+		));
+	}
+}
@@ -0,0 +1,159 @@
+//! Thin wrappers around libwebp's encoders.
+//!
+//! libwebp's animation encoder derives each frame's duration from the
+//! timestamp of the frame after it, so the final timestamp has to be passed
+//! explicitly for the last frame to keep its delay.
+
+use std::mem::MaybeUninit;
+use std::ptr;
+
+use image::RgbaImage;
+use libwebp_sys::*;
+
+use crate::{Error, Frame, Repeat, Result};
+
+pub(crate) fn encode_still(buffer: &RgbaImage) -> Result<Vec<u8>> {
+	let (width, height) = buffer.dimensions();
+	let mut output = ptr::null_mut();
+
+	// SAFETY: `buffer` holds `width * height` tightly packed RGBA pixels and
+	// libwebp allocates `output`, which is copied out and freed below.
+	unsafe {
+		let size = WebPEncodeLosslessRGBA(
+			buffer.as_ptr(),
+			width as i32,
+			height as i32,
+			width as i32 * 4,
+			&mut output,
+		);
+
+		if size == 0 || output.is_null() {
+			return Err(Error::Encode("WebP encoding failed".into()));
+		}
+
+		let bytes = std::slice::from_raw_parts(output, size).to_vec();
+		WebPFree(output.cast());
+
+		Ok(bytes)
+	}
+}
+
+struct AnimEncoder(*mut WebPAnimEncoder);
+
+impl Drop for AnimEncoder {
+	fn drop(&mut self) {
+		// SAFETY: the pointer came from `WebPAnimEncoderNewInternal` and is only
+		// deleted here.
+		unsafe { WebPAnimEncoderDelete(self.0) }
+	}
+}
+
+pub(crate) fn encode_animation(frames: &[Frame], repeat: Repeat) -> Result<Vec<u8>> {
+	let (width, height) = frames[0].buffer.dimensions();
+
+	// SAFETY: every struct passed to libwebp is initialised through its
+	// matching `*Init*` function first, pictures are freed after being added,
+	// and the assembled data is copied out before being cleared.
+	unsafe {
+		let mut options = MaybeUninit::<WebPAnimEncoderOptions>::uninit();
+
+		if WebPAnimEncoderOptionsInitInternal(options.as_mut_ptr(), WebPGetMuxABIVersion()) == 0 {
+			return Err(Error::Encode(
+				"WebP animation options are incompatible".into(),
+			));
+		}
+
+		let mut options = options.assume_init();
+		options.anim_params.bgcolor = 0;
+		options.anim_params.loop_count = match repeat {
+			Repeat::Infinite => 0,
+			Repeat::Finite(count) => i32::from(count) + 1,
+		};
+
+		let encoder = AnimEncoder(WebPAnimEncoderNewInternal(
+			width as i32,
+			height as i32,
+			&options,
+			WebPGetMuxABIVersion(),
+		));
+
+		if encoder.0.is_null() {
+			return Err(Error::Encode(
+				"WebP animation encoder could not be created".into(),
+			));
+		}
+
+		let mut config = MaybeUninit::<WebPConfig>::uninit();
+
+		if WebPConfigInitInternal(
+			config.as_mut_ptr(),
+			WebPPreset::WEBP_PRESET_DEFAULT,
+			90.0,
+			WEBP_ENCODER_ABI_VERSION as i32,
+		) == 0
+		{
+			return Err(Error::Encode("WebP config is incompatible".into()));
+		}
+
+		let mut config = config.assume_init();
+		config.lossless = 1;
+
+		let mut timestamp = 0i32;
+
+		for frame in frames {
+			let mut picture = WebPPicture::new()
+				.map_err(|_| Error::Encode("WebP picture could not be created".into()))?;
+			picture.use_argb = 1;
+			picture.width = width as i32;
+			picture.height = height as i32;
+
+			if WebPPictureImportRGBA(&mut picture, frame.buffer.as_ptr(), width as i32 * 4) == 0 {
+				WebPPictureFree(&mut picture);
+				return Err(Error::Encode("WebP frame could not be imported".into()));
+			}
+
+			let added = WebPAnimEncoderAdd(encoder.0, &mut picture, timestamp, &config);
+			WebPPictureFree(&mut picture);
+
+			if added == 0 {
+				return Err(anim_error(&encoder));
+			}
+
+			// Timestamps are in milliseconds, which an animation long enough
+			// runs out of.
+			timestamp = i32::try_from(frame.delay.as_millis())
+				.ok()
+				.and_then(|delay| timestamp.checked_add(delay.max(1)))
+				.ok_or_else(|| Error::Encode("WebP animation is too long".into()))?;
+		}
+
+		if WebPAnimEncoderAdd(encoder.0, ptr::null_mut(), timestamp, ptr::null()) == 0 {
+			return Err(anim_error(&encoder));
+		}
+
+		let mut data = WebPData::default();
+
+		if WebPAnimEncoderAssemble(encoder.0, &mut data) == 0 {
+			return Err(anim_error(&encoder));
+		}
+
+		let bytes = std::slice::from_raw_parts(data.bytes, data.size).to_vec();
+		WebPDataClear(&mut data);
+
+		Ok(bytes)
+	}
+}
+
+unsafe fn anim_error(encoder: &AnimEncoder) -> Error {
+	let message = WebPAnimEncoderGetError(encoder.0);
+
+	if message.is_null() {
+		Error::Encode("WebP animation encoding failed".into())
+	} else {
+		Error::Encode(
+			std::ffi::CStr::from_ptr(message)
+				.to_string_lossy()
+				.into_owned(),
+		)
+	}
+}
@@ -24,6 +24,10 @@ pub struct Emote {
 
 	#[serde_as(serialize_as = "Vec<DisplayFromStr>")]
 	versions: Vec<i64>,
+
+	#[serde_as(serialize_as = "Option<DisplayFromStr>")]
+	active_version_id: Option<i64>,
+	files: EmoteFiles,
 }
 
 #[serde_as]
@@ -43,38 +47,93 @@ pub struct EmoteWithUser {
 
 	#[serde_as(serialize_as = "Vec<DisplayFromStr>")]
 	versions: Vec<i64>,
+
+	#[serde_as(serialize_as = "Option<DisplayFromStr>")]
+	active_version_id: Option<i64>,
+	files: EmoteFiles,
 	user: User,
 }
 
-#[serde_as]
+/// One stored rendition of an emote. Animated emotes also link to a still
+/// preview of the same size.
 #[derive(Debug, Deserialize, Serialize)]
+pub struct EmoteFile {
+	pub name: String,
+	pub format: String,
+	pub width: i32,
+	pub height: i32,
+	pub size: i64,
+	pub url: String,
+	pub static_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, FromJsonb)]
+pub struct EmoteFiles(pub Vec<EmoteFile>);
+
+#[serde_as]
+#[derive(Debug, Deserialize, Serialize, FromRow)]
 pub struct EmoteVersion {
 	#[serde_as(serialize_as = "DisplayFromStr")]
 	id: i64,
 	name: String,
 	description: String,
+	width: i32,
+	height: i32,
+	animated: bool,
+	files: EmoteFiles,
+	active: bool,
 
 	#[serde_as(serialize_as = "DisplayFromStr")]
 	emote_id: i64,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateEmoteVersion {
+	pub name: String,
+	pub description: String,
+}
+
+#[serde_as]
 #[derive(Debug, Deserialize)]
 pub struct SearchEmotesQuery {
 	#[serde(rename(deserialize = "q"))]
 	pub query: Option<String>,
+	pub animated: Option<bool>,
+	pub modifier: Option<bool>,
+	pub nsfw: Option<bool>,
+	pub approved: Option<bool>,
+	pub public: Option<bool>,
 
+	#[serde_as(as = "Option<DisplayFromStr>")]
 	#[serde(default)]
-	pub filters: String,
+	pub user_id: Option<i64>,
+	pub sort: Option<EmoteSort>,
+	pub limit: Option<usize>,
+	pub offset: Option<usize>,
+}
+
+/// Without a sort, hits are ordered by relevance to the query.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmoteSort {
+	Newest,
+	Popularity,
+	Name,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmoteSearchResults {
+	pub hits: Vec<Emote>,
+	pub total: usize,
+	pub limit: usize,
+	pub offset: usize,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CreateEmote {
 	pub name: String,
 	pub tags: Vec<String>,
-	pub width: i32,
-	pub height: i32,
 	pub public: bool,
-	pub animated: bool,
 	pub modifier: bool,
 	pub nsfw: bool,
 }
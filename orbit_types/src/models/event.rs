@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+/// Something that happened which connected clients may want to react to.
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Event {
+	/// `name` is what the emote goes by in the set, which is its alias if it
+	/// has one.
+	SetEmoteAdded {
+		#[serde_as(as = "DisplayFromStr")]
+		set_id: i64,
+
+		#[serde_as(as = "DisplayFromStr")]
+		emote_id: i64,
+		name: String,
+	},
+
+	SetEmoteRemoved {
+		#[serde_as(as = "DisplayFromStr")]
+		set_id: i64,
+
+		#[serde_as(as = "DisplayFromStr")]
+		emote_id: i64,
+	},
+
+	/// An alias was set or cleared.
+	SetEmoteRenamed {
+		#[serde_as(as = "DisplayFromStr")]
+		set_id: i64,
+
+		#[serde_as(as = "DisplayFromStr")]
+		emote_id: i64,
+		name: String,
+	},
+
+	/// A user switched which of their sets is used in their channel.
+	ChannelSetUpdated {
+		#[serde_as(as = "DisplayFromStr")]
+		user_id: i64,
+
+		#[serde_as(as = "DisplayFromStr")]
+		set_id: i64,
+	},
+
+	/// `set_ids` are the sets the emote is in, so set subscribers hear about it
+	/// too.
+	EmoteUpdated {
+		#[serde_as(as = "DisplayFromStr")]
+		emote_id: i64,
+
+		#[serde_as(as = "DisplayFromStr")]
+		user_id: i64,
+
+		#[serde_as(as = "Vec<DisplayFromStr>")]
+		set_ids: Vec<i64>,
+	},
+
+	/// A moderator approved the emote.
+	EmoteApproved {
+		#[serde_as(as = "DisplayFromStr")]
+		emote_id: i64,
+
+		#[serde_as(as = "DisplayFromStr")]
+		user_id: i64,
+	},
+
+	/// A moderator took back the emote's approval.
+	EmoteRejected {
+		#[serde_as(as = "DisplayFromStr")]
+		emote_id: i64,
+
+		#[serde_as(as = "DisplayFromStr")]
+		user_id: i64,
+	},
+
+	/// `set_ids` are the sets the emote was removed from along with it.
+	EmoteDeleted {
+		#[serde_as(as = "DisplayFromStr")]
+		emote_id: i64,
+
+		#[serde_as(as = "DisplayFromStr")]
+		user_id: i64,
+
+		#[serde_as(as = "Vec<DisplayFromStr>")]
+		set_ids: Vec<i64>,
+	},
+}
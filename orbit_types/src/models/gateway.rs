@@ -0,0 +1,71 @@
+//! Messages exchanged over the event gateway's WebSocket.
+
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use super::event::Event;
+
+/// What a connection wants to hear about.
+#[serde_as]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum Subscription {
+	/// Whichever set a user currently has active in their channel, following
+	/// them when they switch.
+	Channel(#[serde_as(as = "DisplayFromStr")] i64),
+
+	Set(#[serde_as(as = "DisplayFromStr")] i64),
+
+	/// A user's own emotes and channel set switches.
+	User(#[serde_as(as = "DisplayFromStr")] i64),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op", content = "d", rename_all = "snake_case")]
+pub enum ClientMessage {
+	Subscribe(Subscription),
+	Unsubscribe(Subscription),
+
+	/// Has to be sent at least once every `heartbeat_interval`.
+	Heartbeat,
+
+	/// Sent in place of subscribing again after reconnecting, to also get
+	/// whatever was missed after `seq`.
+	Resume {
+		seq: u64,
+		subscriptions: Vec<Subscription>,
+	},
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op", content = "d", rename_all = "snake_case")]
+pub enum ServerMessage {
+	/// The first message on every connection.
+	Hello {
+		/// Milliseconds.
+		heartbeat_interval: u64,
+
+		/// The newest event so far, to resume from later.
+		seq: u64,
+	},
+
+	Dispatch {
+		seq: u64,
+		event: Event,
+	},
+
+	HeartbeatAck,
+	Subscribed(Subscription),
+	Unsubscribed(Subscription),
+
+	/// Everything missed has been replayed.
+	Resumed,
+
+	/// Events were missed that can no longer be replayed, so the client has to
+	/// fetch what it needs again before subscribing.
+	InvalidSession,
+
+	Error {
+		message: String,
+	},
+}
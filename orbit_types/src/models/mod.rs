@@ -1,3 +1,8 @@
 pub mod emote;
+pub mod event;
+pub mod gateway;
+pub mod session;
 pub mod set;
+pub mod token;
 pub mod user;
+pub mod webhook;
@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use orbit_macros::FromRow;
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+/// A session as shown to its owner. The token itself is never returned.
+#[serde_as]
+#[derive(Debug, Serialize, FromRow)]
+pub struct Session {
+	#[serde_as(serialize_as = "DisplayFromStr")]
+	id: i64,
+	user_agent: Option<String>,
+	created_at: DateTime<Utc>,
+	last_used_at: DateTime<Utc>,
+	expires_at: DateTime<Utc>,
+
+	/// Whether this is the session the request was made with.
+	current: bool,
+}
@@ -17,6 +17,9 @@ pub struct EmoteSet {
 
 	#[serde_as(serialize_as = "Option<DisplayFromStr>")]
 	parent_id: Option<i64>,
+
+	#[serde_as(serialize_as = "Option<DisplayFromStr>")]
+	origin_id: Option<i64>,
 }
 
 #[serde_as]
@@ -32,11 +35,35 @@ pub struct EmoteSetWithEmotes {
 
 	#[serde_as(serialize_as = "Option<DisplayFromStr>")]
 	parent_id: Option<i64>,
+
+	#[serde_as(serialize_as = "Option<DisplayFromStr>")]
+	origin_id: Option<i64>,
 	emotes: EmoteVec,
 }
 
+/// An emote as it appears in a set, possibly under an alias. When a set is
+/// resolved, emotes it inherits say which set they came from.
+#[serde_as]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetEmote {
+	#[serde(flatten)]
+	emote: Emote,
+	alias: Option<String>,
+
+	#[serde_as(serialize_as = "Option<DisplayFromStr>")]
+	#[serde(default)]
+	inherited_from: Option<i64>,
+}
+
 #[derive(Debug, Deserialize, Serialize, FromJsonb)]
-struct EmoteVec(Vec<Emote>);
+struct EmoteVec(Vec<SetEmote>);
+
+#[derive(Debug, Deserialize)]
+pub struct GetEmoteSetQuery {
+	/// Include inherited emotes, minus exclusions and overridden names.
+	#[serde(default)]
+	pub resolved: bool,
+}
 
 #[derive(Debug, Deserialize)]
 pub struct CreateEmoteSet {
@@ -44,6 +71,17 @@ pub struct CreateEmoteSet {
 	pub capacity: i32,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SetEmoteAlias {
+	pub alias: String,
+}
+
+/// Forks keep the original's name unless given a new one.
+#[derive(Debug, Default, Deserialize)]
+pub struct ForkEmoteSet {
+	pub name: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct UpdateEmoteSet {
 	pub name: Option<String>,
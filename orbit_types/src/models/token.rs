@@ -0,0 +1,66 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use orbit_macros::FromRow;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use tokio_postgres::types::{FromSql, ToSql};
+
+/// What an API token is allowed to do. Sessions aren't scoped.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, ToSql, FromSql)]
+#[postgres(name = "scope")]
+pub enum Scope {
+	#[serde(rename = "emotes:write")]
+	#[postgres(name = "emotes:write")]
+	EmotesWrite,
+
+	#[serde(rename = "sets:write")]
+	#[postgres(name = "sets:write")]
+	SetsWrite,
+
+	#[serde(rename = "users:read")]
+	#[postgres(name = "users:read")]
+	UsersRead,
+
+	#[serde(rename = "users:write")]
+	#[postgres(name = "users:write")]
+	UsersWrite,
+}
+
+impl fmt::Display for Scope {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::EmotesWrite => "emotes:write",
+			Self::SetsWrite => "sets:write",
+			Self::UsersRead => "users:read",
+			Self::UsersWrite => "users:write",
+		})
+	}
+}
+
+#[serde_as]
+#[derive(Debug, Serialize, FromRow)]
+pub struct ApiToken {
+	#[serde_as(serialize_as = "DisplayFromStr")]
+	id: i64,
+	name: String,
+	scopes: Vec<Scope>,
+	created_at: DateTime<Utc>,
+	last_used_at: Option<DateTime<Utc>>,
+	expires_at: Option<DateTime<Utc>>,
+}
+
+/// A token as returned when it's created, the only time its secret is shown.
+#[derive(Debug, Serialize)]
+pub struct NewApiToken {
+	#[serde(flatten)]
+	pub token: ApiToken,
+	pub secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiToken {
+	pub name: String,
+	pub scopes: Vec<Scope>,
+	pub expires_at: Option<DateTime<Utc>>,
+}
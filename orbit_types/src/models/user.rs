@@ -33,6 +33,29 @@ pub struct User {
 	pub channel_set_id: i64,
 }
 
+/// What an editor may do to the sets of the user they edit for.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, ToSql, FromSql)]
+#[postgres(name = "editor_permission", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EditorPermission {
+	ManageEmotes,
+	RenameSets,
+	ChangeCapacity,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Editor {
+	user: User,
+	permissions: Vec<EditorPermission>,
+}
+
+/// Leaving out `permissions` grants all of them to a new editor and leaves an
+/// existing editor's unchanged.
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateEditor {
+	pub permissions: Option<Vec<EditorPermission>>,
+}
+
 #[serde_as]
 #[derive(Deserialize, Serialize, FromRow)]
 pub struct UserEmoteSet {
@@ -46,6 +69,9 @@ pub struct UserEmoteSet {
 
 	#[serde_as(serialize_as = "Option<DisplayFromStr>")]
 	parent_id: Option<i64>,
+
+	#[serde_as(serialize_as = "Option<DisplayFromStr>")]
+	origin_id: Option<i64>,
 }
 
 #[serde_as]
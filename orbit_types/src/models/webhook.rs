@@ -0,0 +1,119 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use orbit_macros::FromRow;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use tokio_postgres::types::{FromSql, ToSql};
+
+use super::event::Event;
+
+/// The kinds of [`Event`] a webhook can be sent.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, ToSql, FromSql)]
+#[postgres(name = "webhook_event", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+	SetEmoteAdded,
+	SetEmoteRemoved,
+	SetEmoteRenamed,
+	ChannelSetUpdated,
+	EmoteUpdated,
+	EmoteDeleted,
+	EmoteApproved,
+	EmoteRejected,
+}
+
+impl fmt::Display for WebhookEvent {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::SetEmoteAdded => "set_emote_added",
+			Self::SetEmoteRemoved => "set_emote_removed",
+			Self::SetEmoteRenamed => "set_emote_renamed",
+			Self::ChannelSetUpdated => "channel_set_updated",
+			Self::EmoteUpdated => "emote_updated",
+			Self::EmoteDeleted => "emote_deleted",
+			Self::EmoteApproved => "emote_approved",
+			Self::EmoteRejected => "emote_rejected",
+		})
+	}
+}
+
+impl From<&Event> for WebhookEvent {
+	fn from(event: &Event) -> Self {
+		match event {
+			Event::SetEmoteAdded { .. } => Self::SetEmoteAdded,
+			Event::SetEmoteRemoved { .. } => Self::SetEmoteRemoved,
+			Event::SetEmoteRenamed { .. } => Self::SetEmoteRenamed,
+			Event::ChannelSetUpdated { .. } => Self::ChannelSetUpdated,
+			Event::EmoteUpdated { .. } => Self::EmoteUpdated,
+			Event::EmoteDeleted { .. } => Self::EmoteDeleted,
+			Event::EmoteApproved { .. } => Self::EmoteApproved,
+			Event::EmoteRejected { .. } => Self::EmoteRejected,
+		}
+	}
+}
+
+/// A webhook as shown to its owner. The secret is only shown once.
+#[serde_as]
+#[derive(Debug, Serialize, FromRow)]
+pub struct Webhook {
+	#[serde_as(serialize_as = "DisplayFromStr")]
+	id: i64,
+	url: String,
+	events: Vec<WebhookEvent>,
+
+	/// Turned off automatically after too many failed deliveries in a row.
+	enabled: bool,
+	failures: i32,
+	created_at: DateTime<Utc>,
+}
+
+/// A webhook as returned when it's created, along with the secret its
+/// payloads are signed with.
+#[derive(Debug, Serialize)]
+pub struct NewWebhook {
+	#[serde(flatten)]
+	pub webhook: Webhook,
+	pub secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhook {
+	pub url: String,
+	pub events: Vec<WebhookEvent>,
+}
+
+/// Enabling a webhook again also clears its failures.
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhook {
+	pub url: Option<String>,
+	pub events: Option<Vec<WebhookEvent>>,
+	pub enabled: Option<bool>,
+}
+
+#[serde_as]
+#[derive(Debug, Serialize, FromRow)]
+pub struct WebhookDelivery {
+	#[serde_as(serialize_as = "DisplayFromStr")]
+	id: i64,
+	event: WebhookEvent,
+	attempts: i32,
+	status_code: Option<i32>,
+	error: Option<String>,
+	success: Option<bool>,
+	created_at: DateTime<Utc>,
+	completed_at: Option<DateTime<Utc>>,
+}
+
+/// The body of every webhook request. `id` stays the same across retries of
+/// one delivery.
+#[serde_as]
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload<'a> {
+	#[serde_as(serialize_as = "DisplayFromStr")]
+	pub id: i64,
+
+	#[serde_as(serialize_as = "DisplayFromStr")]
+	pub webhook_id: i64,
+	pub event: &'a Event,
+}
@@ -29,4 +29,9 @@ impl Snowflake {
 		let id = ((now_ms - Self::EPOCH) << 22) | (increment & 4095i64);
 		Self(id)
 	}
+
+	/// When the id was generated, in milliseconds since the Unix epoch.
+	pub fn timestamp(&self) -> i64 {
+		(self.0 >> 22) + Self::EPOCH
+	}
 }